@@ -0,0 +1,467 @@
+use crate::bot::shutdown_signal;
+use crate::config::{self, Config, Release, Triage};
+use crate::{normalize, release, AppError};
+use chrono::NaiveDate;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, info};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 500;
+
+// A release as returned by the API, along with its ID
+#[derive(Serialize)]
+struct Item<'a> {
+    id: String,
+    #[serde(flatten)]
+    release: &'a Release,
+}
+
+impl<'a> Item<'a> {
+    fn new(release: &'a Release) -> Self {
+        Self {
+            id: release::id(release),
+            release,
+        }
+    }
+}
+
+// An error response
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+impl From<AppError> for ApiError {
+    fn from(e: AppError) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+fn bad_request(message: String) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, message)
+}
+
+fn not_found(message: String) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, message)
+}
+
+// Where requests read and write releases; the release store, except in tests
+trait Store {
+    fn load(&self) -> Result<Config, AppError>;
+
+    // Changes the store as it is now, under the store lock
+    fn update(
+        &self,
+        change: &mut dyn FnMut(&mut Config) -> Result<(), AppError>,
+    ) -> Result<Config, AppError>;
+}
+
+struct ReleaseStore;
+
+impl Store for ReleaseStore {
+    fn load(&self) -> Result<Config, AppError> {
+        config::load_config()
+    }
+
+    fn update(
+        &self,
+        change: &mut dyn FnMut(&mut Config) -> Result<(), AppError>,
+    ) -> Result<Config, AppError> {
+        config::update(change)
+    }
+}
+
+// Serves the release store as JSON; the store is re-read on every request so
+// responses include releases from later runs
+pub async fn serve(addr: SocketAddr) -> Result<(), AppError> {
+    let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+
+    info!("Serving API on http://{}", addr);
+
+    Server::try_bind(&addr)?
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    Ok(())
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    debug!("{} {}", req.method(), req.uri());
+
+    let (status, body) = match route(&req, &ReleaseStore) {
+        Ok(body) => (StatusCode::OK, body),
+        Err(ApiError(status, message)) => (status, json!({ "error": message })),
+    };
+
+    let mut resp = Response::new(Body::from(body.to_string()));
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+
+    Ok(resp)
+}
+
+fn route(req: &Request<Body>, store: &dyn Store) -> Result<Value, ApiError> {
+    let segments: Vec<String> = req
+        .uri()
+        .path()
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().to_string())
+        .collect();
+
+    let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+
+    let query: HashMap<String, String> =
+        url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+            .into_owned()
+            .collect();
+
+    match (req.method(), segments.as_slice()) {
+        (&Method::GET, ["releases"]) => list_releases(&store.load()?, &query),
+        (&Method::GET, ["releases", id]) => {
+            let config = store.load()?;
+            Ok(json!(Item::new(find(&config, id)?)))
+        }
+        (&Method::GET, ["artists", name]) => artist(&store.load()?, name),
+        (&Method::POST, ["releases", id, "skip"]) => skip(store, id),
+        (_, ["releases"] | ["releases", _] | ["artists", _] | ["releases", _, "skip"]) => {
+            Err(ApiError(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Method not allowed".to_string(),
+            ))
+        }
+        _ => Err(not_found(format!(
+            "No such endpoint '{}'",
+            req.uri().path()
+        ))),
+    }
+}
+
+fn find<'a>(config: &'a Config, id: &str) -> Result<&'a Release, ApiError> {
+    config
+        .releases
+        .iter()
+        .find(|r| release::id(r) == id)
+        .ok_or_else(|| not_found(format!("No release with ID '{}'", id)))
+}
+
+// GET /releases?from&to&genre&country&label&valid&page&per_page
+fn list_releases(config: &Config, query: &HashMap<String, String>) -> Result<Value, ApiError> {
+    let date = |name: &str| {
+        query
+            .get(name)
+            .map(|d| {
+                NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                    .map_err(|_| bad_request(format!("Invalid date '{}' for '{}'", d, name)))
+            })
+            .transpose()
+    };
+
+    let number = |name: &str, default: usize| {
+        query
+            .get(name)
+            .map(|n| {
+                n.parse::<usize>()
+                    .map_err(|_| bad_request(format!("Invalid number '{}' for '{}'", n, name)))
+            })
+            .unwrap_or(Ok(default))
+    };
+
+    let contains = |name: &str, value: &str| {
+        query
+            .get(name)
+            .is_none_or(|q| value.to_lowercase().contains(&q.to_lowercase()))
+    };
+
+    let from = date("from")?;
+    let to = date("to")?;
+    let valid = query.get("valid").map(|v| v == "true");
+    let page = number("page", 1)?.max(1);
+    let per_page = number("per_page", DEFAULT_PER_PAGE)?.clamp(1, MAX_PER_PAGE);
+
+    let valid_ids: Vec<String> = release::stored_valid_releases(config)
+        .iter()
+        .map(|r| release::id(r))
+        .collect();
+
+    let mut releases: Vec<&Release> = config
+        .releases
+        .iter()
+        .filter(|r| from.is_none_or(|from| r.date >= from))
+        .filter(|r| to.is_none_or(|to| r.date <= to))
+        .filter(|r| {
+            let (genre, country) = match &r.metallum {
+                Some(m) => (m.genre.as_str(), m.country_origin.as_str()),
+                None => ("", ""),
+            };
+
+            contains("genre", genre) && contains("country", country) && contains("label", &r.label)
+        })
+        .filter(|r| valid.is_none_or(|valid| valid_ids.contains(&release::id(r)) == valid))
+        .collect();
+
+    releases.sort_by_key(|r| r.date);
+
+    let total = releases.len();
+    let items: Vec<Item> = releases
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .map(Item::new)
+        .collect();
+
+    Ok(json!({
+        "releases": items,
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+    }))
+}
+
+// GET /artists/{name}; metadata comes from the artist's most recently enriched release
+fn artist(config: &Config, name: &str) -> Result<Value, ApiError> {
+    let releases: Vec<&Release> = config
+        .releases
+        .iter()
+        .filter(|r| normalize::same_artist(&r.artist, name))
+        .collect();
+
+    let first = releases
+        .first()
+        .ok_or_else(|| not_found(format!("No artist named '{}'", name)))?;
+
+    Ok(json!({
+        "name": first.artist,
+        "spotify": releases.iter().rev().find_map(|r| r.spotify.as_ref()),
+        "metallum": releases.iter().rev().find_map(|r| r.metallum.as_ref()),
+        "releases": releases.iter().map(|r| Item::new(r)).collect::<Vec<Item>>(),
+    }))
+}
+
+// POST /releases/{id}/skip; same as marking the release as skip in the TUI.
+// Only the mark is written, so a job saving the store at the same time (or
+// another request) doesn't lose it.
+fn skip(store: &dyn Store, id: &str) -> Result<Value, ApiError> {
+    let config = store.update(&mut |config| {
+        if let Some(release) = config.releases.iter_mut().find(|r| release::id(r) == id) {
+            release.triage = Some(Triage::Skip);
+        }
+
+        Ok(())
+    })?;
+
+    let release = find(&config, id)?;
+
+    info!("Marked release '{}' as skip", id);
+
+    Ok(json!(Item::new(release)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{MetallumArtistInfo, SpotifyArtistInfo};
+    use std::cell::RefCell;
+
+    // Keeps the store as JSON, like the file
+    struct MemoryStore(RefCell<Value>);
+
+    impl Store for MemoryStore {
+        fn load(&self) -> Result<Config, AppError> {
+            Ok(serde_json::from_value(self.0.borrow().clone())?)
+        }
+
+        fn update(
+            &self,
+            change: &mut dyn FnMut(&mut Config) -> Result<(), AppError>,
+        ) -> Result<Config, AppError> {
+            let mut config = self.load()?;
+            change(&mut config)?;
+            *self.0.borrow_mut() = serde_json::to_value(&config)?;
+
+            Ok(config)
+        }
+    }
+
+    fn release(date: &str, artist: &str, label: &str, genre: Option<&str>) -> Release {
+        Release {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            artist: artist.to_string(),
+            album: "Album".to_string(),
+            label: label.to_string(),
+            skip: false,
+            skip_reasons: vec![],
+            spotify: genre.map(|_| SpotifyArtistInfo::default()),
+            metallum: genre.map(|genre| MetallumArtistInfo {
+                genre: genre.to_string(),
+                country_origin: "Norway".to_string(),
+                ..Default::default()
+            }),
+            updated: None,
+            triage: None,
+            status: None,
+            date_history: vec![],
+            followed: false,
+            announced: false,
+            looked_up: false,
+            notified: false,
+        }
+    }
+
+    fn store() -> MemoryStore {
+        let config = Config {
+            releases: vec![
+                release(
+                    "2023-03-10",
+                    "Enslaved",
+                    "Nuclear Blast",
+                    Some("Black Metal"),
+                ),
+                release(
+                    "2023-03-03",
+                    "Opeth",
+                    "Reigning Phoenix",
+                    Some("Progressive Metal"),
+                ),
+                release("2023-03-17", "Gojira", "Roadrunner", None),
+                release("2023-03-24", "Kvelertak", "Rise Above", Some("Black Metal")),
+            ],
+            ..Default::default()
+        };
+
+        MemoryStore(RefCell::new(serde_json::to_value(&config).unwrap()))
+    }
+
+    fn request(store: &MemoryStore, method: Method, uri: &str) -> Result<Value, ApiError> {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+
+        route(&req, store)
+    }
+
+    fn artists(body: &Value) -> Vec<&str> {
+        body["releases"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["artist"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn list_filters() {
+        let store = store();
+        let get = |uri: &str| request(&store, Method::GET, uri).unwrap();
+
+        assert_eq!(
+            artists(&get("/releases")),
+            vec!["Opeth", "Enslaved", "Gojira", "Kvelertak"]
+        );
+        assert_eq!(
+            artists(&get("/releases?from=2023-03-10&to=2023-03-17")),
+            vec!["Enslaved", "Gojira"]
+        );
+        assert_eq!(
+            artists(&get("/releases?genre=black")),
+            vec!["Enslaved", "Kvelertak"]
+        );
+        assert_eq!(artists(&get("/releases?label=nuclear")), vec!["Enslaved"]);
+        assert_eq!(
+            artists(&get("/releases?country=sweden")),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            artists(&get("/releases?valid=true")),
+            vec!["Opeth", "Enslaved", "Kvelertak"]
+        );
+        assert_eq!(artists(&get("/releases?valid=false")), vec!["Gojira"]);
+    }
+
+    #[test]
+    fn list_pages() {
+        let store = store();
+        let body = request(&store, Method::GET, "/releases?page=2&per_page=3").unwrap();
+
+        assert_eq!(artists(&body), vec!["Kvelertak"]);
+        assert_eq!(body["total"], 4);
+        assert_eq!(body["page"], 2);
+        assert_eq!(body["per_page"], 3);
+
+        let body = request(&store, Method::GET, "/releases?page=0&per_page=100000").unwrap();
+
+        assert_eq!(body["page"], 1);
+        assert_eq!(body["per_page"], MAX_PER_PAGE);
+    }
+
+    #[test]
+    fn errors() {
+        let store = store();
+        let status = |method: Method, uri: &str| request(&store, method, uri).unwrap_err().0;
+
+        assert_eq!(
+            status(Method::GET, "/releases?from=2023-13-01"),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(Method::GET, "/releases?page=two"),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(status(Method::GET, "/nope"), StatusCode::NOT_FOUND);
+        assert_eq!(
+            status(Method::GET, "/releases/nope-album"),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(Method::GET, "/artists/Mayhem"),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(Method::POST, "/releases/nope-album/skip"),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(Method::DELETE, "/releases"),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(
+            status(Method::GET, "/releases/enslaved-album/skip"),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
+
+    #[test]
+    fn get_release_and_artist() {
+        let store = store();
+
+        let body = request(&store, Method::GET, "/releases/enslaved-album").unwrap();
+        assert_eq!(body["artist"], "Enslaved");
+        assert_eq!(body["id"], "enslaved-album");
+
+        let body = request(&store, Method::GET, "/artists/ENSLAVED").unwrap();
+        assert_eq!(body["name"], "Enslaved");
+        assert_eq!(body["metallum"]["genre"], "Black Metal");
+    }
+
+    #[test]
+    fn skip_marks_release() {
+        let store = store();
+
+        let body = request(&store, Method::POST, "/releases/opeth-album/skip").unwrap();
+        assert_eq!(body["triage"], "skip");
+
+        let body = request(&store, Method::GET, "/releases?valid=true").unwrap();
+        assert_eq!(artists(&body), vec!["Enslaved", "Kvelertak"]);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::bot::{shutdown_signal, Bot};
+use crate::AppError;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, error, info, warn};
+use slack::event_api::event::EventRequest;
+use slack::event_api::signature::{verify_signature, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use slack_rust as slack;
+use std::collections::{HashSet, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+// How many event IDs are remembered; Slack gives up retrying within an hour
+const RECENT_EVENTS: usize = 1000;
+
+// Recently received event IDs. Slack redelivers events it didn't get a timely
+// response to with the same ID, whether or not the first delivery arrived.
+#[derive(Default)]
+struct RecentEvents {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl RecentEvents {
+    // False if the event has been received before
+    fn insert(&mut self, id: &str) -> bool {
+        if !self.ids.insert(id.to_string()) {
+            return false;
+        }
+
+        self.order.push_back(id.to_string());
+
+        if self.order.len() > RECENT_EVENTS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+
+        true
+    }
+}
+
+// Receives Events API requests over HTTP
+pub async fn serve(
+    bot: Arc<Bot>,
+    addr: SocketAddr,
+    signing_secret: String,
+) -> Result<(), AppError> {
+    let signing_secret = Arc::new(signing_secret);
+    let recent = Arc::new(Mutex::new(RecentEvents::default()));
+
+    let make_svc = make_service_fn(move |_conn| {
+        let bot = bot.clone();
+        let signing_secret = signing_secret.clone();
+        let recent = recent.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(req, bot.clone(), signing_secret.clone(), recent.clone())
+            }))
+        }
+    });
+
+    info!("Listening for Slack events on http://{}", addr);
+
+    Server::try_bind(&addr)?
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    Ok(())
+}
+
+async fn handle(
+    req: Request<Body>,
+    bot: Arc<Bot>,
+    signing_secret: Arc<String>,
+    recent: Arc<Mutex<RecentEvents>>,
+) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::POST {
+        return Ok(respond(StatusCode::METHOD_NOT_ALLOWED, Body::empty()));
+    }
+
+    let timestamp = header(&req, TIMESTAMP_HEADER);
+    let signature = header(&req, SIGNATURE_HEADER);
+
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Could not read request body: {}", e);
+            return Ok(respond(StatusCode::BAD_REQUEST, Body::empty()));
+        }
+    };
+
+    // Signature is computed over the raw body, so verify before parsing
+    let body = String::from_utf8_lossy(&body);
+
+    if let Err(e) = verify_signature(&signing_secret, &timestamp, &body, &signature) {
+        warn!("Rejecting request with invalid signature: {}", e);
+        return Ok(respond(StatusCode::UNAUTHORIZED, Body::empty()));
+    }
+
+    let request = match serde_json::from_str::<EventRequest>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Could not parse event request: {}", e);
+            return Ok(respond(StatusCode::BAD_REQUEST, Body::empty()));
+        }
+    };
+
+    match request {
+        EventRequest::UrlVerification(v) => {
+            debug!("Answering url verification challenge");
+
+            let json = serde_json::json!({ "challenge": v.challenge }).to_string();
+            let mut resp = respond(StatusCode::OK, Body::from(json));
+            resp.headers_mut().insert(
+                hyper::header::CONTENT_TYPE,
+                hyper::header::HeaderValue::from_static("application/json"),
+            );
+
+            Ok(resp)
+        }
+        EventRequest::EventCallback(e) => {
+            // Slack retries when we are slow to respond; the original delivery
+            // is already being handled
+            let first = recent
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(&e.event_id);
+
+            if !first {
+                debug!("Ignoring repeated delivery of event '{}'", e.event_id);
+                return Ok(respond(StatusCode::OK, Body::empty()));
+            }
+
+            // Slack expects a response within 3 seconds
+            tokio::spawn(async move {
+                if let Err(err) = bot.handle_event(e.event).await {
+                    error!("Could not handle event '{}': {}", e.event_id, err);
+                }
+            });
+
+            Ok(respond(StatusCode::OK, Body::empty()))
+        }
+    }
+}
+
+fn header(req: &Request<Body>, name: &str) -> String {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn respond(status: StatusCode, body: Body) -> Response<Body> {
+    let mut resp = Response::new(body);
+    *resp.status_mut() = status;
+    resp
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Config;
+    use slack::event_api::signature::sign;
+
+    const SECRET: &str = "8f742231b10e8888abcd99yyyzzz85a5";
+
+    #[test]
+    fn recent_events() {
+        let mut recent = RecentEvents::default();
+
+        assert!(recent.insert("Ev0"));
+        assert!(!recent.insert("Ev0"));
+
+        for i in 1..=RECENT_EVENTS {
+            assert!(recent.insert(&format!("Ev{}", i)));
+        }
+
+        // The oldest is forgotten
+        assert!(recent.insert("Ev0"));
+        assert_eq!(recent.ids.len(), RECENT_EVENTS);
+    }
+
+    // Serves handle() on a free local port
+    fn start() -> (String, Arc<Mutex<RecentEvents>>) {
+        let bot = Arc::new(Bot::new(&Config::default()));
+        let signing_secret = Arc::new(SECRET.to_string());
+        let recent = Arc::new(Mutex::new(RecentEvents::default()));
+
+        let make_svc = {
+            let recent = recent.clone();
+
+            make_service_fn(move |_conn| {
+                let (bot, signing_secret, recent) =
+                    (bot.clone(), signing_secret.clone(), recent.clone());
+
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req| {
+                        handle(req, bot.clone(), signing_secret.clone(), recent.clone())
+                    }))
+                }
+            })
+        };
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        (url, recent)
+    }
+
+    async fn post(url: &str, body: &str, secret: &str, retry: bool) -> reqwest::Response {
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+
+        let mut req = reqwest::Client::new()
+            .post(url)
+            .header(TIMESTAMP_HEADER, &timestamp)
+            .header(SIGNATURE_HEADER, sign(secret, &timestamp, body).unwrap())
+            .body(body.to_string());
+
+        if retry {
+            req = req.header("X-Slack-Retry-Num", "1");
+        }
+
+        req.send().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn signed_requests() {
+        let (url, _) = start();
+
+        let resp = reqwest::get(&url).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let body = r#"{"token":"t","challenge":"3eZbrw1aBm2rZgRNFdxV2595E9CY3gmdALWMmHkvFXO7tYXAYM8P","type":"url_verification"}"#;
+
+        let resp = post(&url, body, "wrong", false).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let resp = post(&url, body, SECRET, false).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.json::<serde_json::Value>().await.unwrap()["challenge"],
+            "3eZbrw1aBm2rZgRNFdxV2595E9CY3gmdALWMmHkvFXO7tYXAYM8P"
+        );
+
+        let resp = post(&url, "{", SECRET, false).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn repeated_events() {
+        let (url, recent) = start();
+
+        let event = |id: &str| {
+            serde_json::json!({
+                "token": "t",
+                "team_id": "T1234567890",
+                "api_app_id": "A0000000000",
+                "event": {
+                    "type": "app_home_opened",
+                    "user": "U061F7AUR",
+                    "channel": "D0LAN2Q65",
+                    "event_ts": "1515449522000016",
+                    "tab": "home",
+                    "view": { "id": "VPASKP233" }
+                },
+                "event_id": id,
+                "event_time": 1600000000,
+                "type": "event_callback"
+            })
+            .to_string()
+        };
+
+        // A retry of an event that never arrived is handled; its next retry isn't
+        for (id, retry) in [("Ev1", false), ("Ev2", true), ("Ev2", true), ("Ev1", true)] {
+            let resp = post(&url, &event(id), SECRET, retry).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        let recent = recent.lock().unwrap();
+        assert_eq!(recent.order, ["Ev1", "Ev2"]);
+    }
+}
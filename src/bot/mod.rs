@@ -0,0 +1,153 @@
+mod events;
+mod socket;
+
+use crate::config::{Config, Release};
+use crate::{config, release, AppError};
+use log::{debug, info};
+use slack::chat::post_message::{post_message, PostMessageRequest};
+use slack::event_api::event::EventCallback;
+use slack::http_client::default_client;
+use slack_rust as slack;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+// Event handling shared by every transport (socket mode and HTTP)
+pub struct Bot {
+    token: String,
+}
+
+impl Bot {
+    pub fn new(cfg: &Config) -> Self {
+        Self {
+            token: cfg.slack_bot_token.clone(),
+        }
+    }
+
+    pub async fn handle_event(&self, event: EventCallback) -> Result<(), AppError> {
+        match event {
+            EventCallback::AppMention {
+                channel,
+                ts,
+                thread_ts,
+                user,
+                ..
+            } => {
+                info!("Mentioned by '{}' in channel '{}'", user, channel);
+                self.reply_releases(&channel, thread_ts.unwrap_or(ts)).await
+            }
+            e => {
+                debug!("Ignoring event: {:?}", e);
+                Ok(())
+            }
+        }
+    }
+
+    // Replies (in a thread) with today's releases from the release store
+    async fn reply_releases(&self, channel: &str, thread_ts: String) -> Result<(), AppError> {
+        let config = config::load_config()?;
+
+        // Only enriched releases can be ranked; followed ones are listed regardless
+        let releases_today: Vec<Release> = release::get_releases_today(&config.releases)
+            .into_iter()
+            .filter(|r| r.followed || (r.spotify.is_some() && r.metallum.is_some()))
+            .collect();
+
+        let valid_releases = release::filter_valid_releases(&config, &releases_today);
+
+        let text = if valid_releases.is_empty() {
+            "No interesting releases today :disappointed:".to_string()
+        } else {
+            let lines: Vec<String> = valid_releases
+                .iter()
+                .enumerate()
+                .map(|(i, r)| {
+                    let metallum = r.metallum.clone().unwrap_or_default();
+
+                    format!(
+                        "{}. <{}|{} - {}> ({})",
+                        i + 1,
+                        metallum.url,
+                        r.artist,
+                        r.album,
+                        metallum.genre
+                    )
+                })
+                .collect();
+
+            format!(
+                ":metal: *{}* releases today:\n{}",
+                valid_releases.len(),
+                lines.join("\n")
+            )
+        };
+
+        let param = PostMessageRequest {
+            channel: channel.to_string(),
+            thread_ts: Some(thread_ts),
+            text: Some(text),
+            ..Default::default()
+        };
+
+        post_message(&default_client(), &param, &self.token).await?;
+
+        Ok(())
+    }
+}
+
+// Resolves on Ctrl-C or SIGTERM so the bot can disconnect cleanly
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.ok();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Received shutdown signal");
+}
+
+pub async fn run(config: &Config, listen: Option<SocketAddr>) -> Result<(), AppError> {
+    if config.slack_bot_token.is_empty() {
+        return Err(AppError::SlackError(
+            "Slack bot token is not configured".to_string(),
+        ));
+    }
+
+    let bot = Arc::new(Bot::new(config));
+
+    match listen {
+        Some(addr) => {
+            if config.slack_signing_secret.is_empty() {
+                return Err(AppError::SlackError(
+                    "Slack signing secret is required to receive events over HTTP".to_string(),
+                ));
+            }
+
+            events::serve(bot, addr, config.slack_signing_secret.clone()).await
+        }
+        None => {
+            if config.slack_app_token.is_empty() {
+                return Err(AppError::SlackError(
+                    "Slack app token is required for socket mode".to_string(),
+                ));
+            }
+
+            socket::run(bot, config).await
+        }
+    }
+}
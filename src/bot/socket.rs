@@ -0,0 +1,69 @@
+use crate::bot::{shutdown_signal, Bot};
+use crate::config::Config;
+use crate::AppError;
+use async_trait::async_trait;
+use log::{error, info};
+use slack::http_client::{default_client, SlackWebAPIClient};
+use slack::socket::event::{DisconnectEvent, EventsAPI};
+use slack::socket::socket_mode::{ack, EventHandler, SocketMode, Stream};
+use slack_rust as slack;
+use std::sync::Arc;
+use std::time::Duration;
+
+struct Handler {
+    bot: Arc<Bot>,
+}
+
+#[async_trait]
+impl<S> EventHandler<S> for Handler
+where
+    S: SlackWebAPIClient,
+{
+    async fn on_connect(&mut self, _socket_mode: &SocketMode<S>) {
+        info!("Connected to Slack in socket mode");
+    }
+
+    async fn on_close(&mut self, _socket_mode: &SocketMode<S>) {
+        info!("Disconnected from Slack");
+    }
+
+    async fn on_disconnect(
+        &mut self,
+        _socket_mode: &SocketMode<S>,
+        e: DisconnectEvent,
+        _s: &mut Stream,
+    ) {
+        info!("Slack requested disconnect: {:?}", e.reason);
+    }
+
+    async fn on_events_api(&mut self, _socket_mode: &SocketMode<S>, e: EventsAPI, s: &mut Stream) {
+        // Slack expects an ack within 3 seconds, so do it before handling the event
+        if let Err(e) = ack(&e.envelope_id, s).await {
+            error!("Could not ack event: {}", e);
+        }
+
+        // Handled in the background so a slow event doesn't hold up the ones after it
+        let bot = self.bot.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = bot.handle_event(e.payload.event).await {
+                error!("Could not handle event: {}", e);
+            }
+        });
+    }
+}
+
+pub async fn run(bot: Arc<Bot>, config: &Config) -> Result<(), AppError> {
+    SocketMode::new(
+        default_client(),
+        config.slack_app_token.clone(),
+        config.slack_bot_token.clone(),
+    )
+    .auto_reconnect(true)
+    .reconnect_backoff(Duration::from_secs(1), Duration::from_secs(120))
+    .shutdown_signal(shutdown_signal())
+    .run(&mut Handler { bot })
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use crate::config::{Config, Release, ReleaseStatus, Triage};
+use crate::server::Document;
+use crate::{export, release};
+use chrono::{Local, NaiveDate};
+
+pub const DOCUMENT: Document = Document {
+    path: "/metalpal.ics",
+    content_type: "text/calendar; charset=utf-8",
+    render: ics,
+};
+
+pub fn ics(config: &Config) -> String {
+    export::ics(&releases(config, Local::now().date_naive()))
+}
+
+// Releases that made it through filtering, plus upcoming ones that haven't been
+// enriched (and filtered) yet, which are marked as tentative
+fn releases(config: &Config, today: NaiveDate) -> Vec<&Release> {
+    let mut releases = release::stored_valid_releases(config);
+
+    releases.extend(config.releases.iter().filter(|r| {
+        r.date >= today
+            && !(r.spotify.is_some() && r.metallum.is_some())
+            && !r.skip
+            && r.triage != Some(Triage::Skip)
+            && r.status != Some(ReleaseStatus::Cancelled)
+    }));
+
+    releases.sort_by_key(|r| r.date);
+
+    releases
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{MetallumArtistInfo, SpotifyArtistInfo};
+
+    fn release(date: &str, artist: &str, enriched: bool) -> Release {
+        let mut release: Release = serde_json::from_value(serde_json::json!({
+            "date": date,
+            "artist": artist,
+            "album": "Album",
+            "label": "",
+            "skip_reasons": [],
+        }))
+        .unwrap();
+
+        if enriched {
+            release.spotify = Some(SpotifyArtistInfo::default());
+            release.metallum = Some(MetallumArtistInfo::default());
+        }
+
+        release
+    }
+
+    #[test]
+    fn upcoming_releases() {
+        let mut skipped = release("2023-04-21", "Nickelback", false);
+        skipped.skip = true;
+
+        let mut marked = release("2023-04-21", "Metallica", false);
+        marked.triage = Some(Triage::Skip);
+
+        let mut cancelled = release("2023-04-21", "Gojira", false);
+        cancelled.status = Some(ReleaseStatus::Cancelled);
+
+        let config = Config {
+            releases: vec![
+                release("2023-04-07", "Opeth", true),
+                release("2023-04-07", "Unenriched", false),
+                release("2023-04-21", "Enslaved", false),
+                skipped,
+                marked,
+                cancelled,
+            ],
+            ..Default::default()
+        };
+
+        let today = NaiveDate::from_ymd_opt(2023, 4, 14).unwrap();
+        let artists: Vec<&str> = releases(&config, today)
+            .iter()
+            .map(|r| r.artist.as_str())
+            .collect();
+
+        assert_eq!(artists, vec!["Opeth", "Enslaved"]);
+
+        let ics = export::ics(&releases(&config, today));
+        let events: Vec<&str> = ics.split("BEGIN:VEVENT").skip(1).collect();
+
+        assert!(!events[0].contains("STATUS:TENTATIVE"));
+        assert!(events[1].contains("STATUS:TENTATIVE"));
+    }
+}
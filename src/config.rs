@@ -1,18 +1,22 @@
 use chrono::prelude::NaiveDate;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::io;
 use std::io::Write;
 
 use crate::error::AppError;
+use crate::lock::StoreLock;
+use crate::merge;
 
 const CONFIG_FILE: &str = ".metalpal.json";
 
 // Q: This needs to be in main.rs for some reason, otherwise it panics; how can
 // I move this into config.rs?
 #[derive(Parser, Debug)]
-pub struct CLI {
+#[command(subcommand_negates_reqs = true)]
+pub struct Cli {
     /// Enable debug output
     #[arg(short, long, env = "METALPAL_DEBUG")]
     pub debug: bool,
@@ -39,12 +43,115 @@ pub struct CLI {
     #[arg(long, env = "METALPAL_SLACK_CHANNELS", default_value = "")]
     pub slack_channels: Vec<String>,
 
+    #[arg(
+        long,
+        env = "METALPAL_SLACK_APP_TOKEN",
+        default_value = "",
+        help = "Slack app-level token; used by the bot in socket mode"
+    )]
+    pub slack_app_token: String,
+
+    #[arg(
+        long,
+        env = "METALPAL_SLACK_SIGNING_SECRET",
+        default_value = "",
+        help = "Slack signing secret; used to verify requests when the bot receives events over HTTP"
+    )]
+    pub slack_signing_secret: String,
+
+    #[arg(
+        long,
+        env = "METALPAL_SLACK_DELIVERY_TIME",
+        help = "Schedule Slack digests for this local time (HH:MM) instead of posting immediately"
+    )]
+    pub slack_delivery_time: Option<String>,
+
+    #[arg(
+        long,
+        env = "METALPAL_SLACK_TIMEZONE",
+        help = "Timezone for --slack-delivery-time (ie. 'Europe/Berlin'); defaults to system timezone"
+    )]
+    pub slack_timezone: Option<String>,
+
+    #[arg(
+        long,
+        env = "METALPAL_SLACK_JOIN_CHANNELS",
+        help = "Join public Slack channels the bot is not a member of yet"
+    )]
+    pub slack_join_channels: bool,
+
+    #[arg(
+        long,
+        env = "METALPAL_SLACK_WEEKLY_EXPORT",
+        value_name = "WEEKDAY",
+        help = "Upload the coming week's releases as CSV and ICS files on this day (ie. 'mon')"
+    )]
+    pub slack_weekly_export: Option<String>,
+
+    #[arg(
+        long,
+        env = "METALPAL_SLACK_UPLOAD_IMAGES",
+        help = "Upload band images to Slack instead of linking to Metal Archives"
+    )]
+    pub slack_upload_images: bool,
+
+    #[arg(
+        long,
+        env = "METALPAL_SLACK_FEEDBACK",
+        help = "Learn from reactions on posted releases (see feedback_reactions)"
+    )]
+    pub slack_feedback: bool,
+
+    #[arg(
+        long,
+        env = "METALPAL_NOTIFY_DATE_CHANGES",
+        help = "Tell Slack and the notifiers when a release is postponed or disappears"
+    )]
+    pub notify_date_changes: bool,
+
+    #[arg(
+        long,
+        env = "METALPAL_FETCH_INTERVAL_HOURS",
+        default_value_t = 24,
+        help = "Fetch the upcoming releases list again when it is older than this"
+    )]
+    pub fetch_interval_hours: u64,
+
+    #[arg(
+        long,
+        env = "METALPAL_SITE_DIR",
+        help = "Regenerate a static site of the release archive in this directory after each run"
+    )]
+    pub site_dir: Option<String>,
+
+    #[arg(
+        long,
+        env = "METALPAL_SCRAPER_MAX_FAILURE_RATIO",
+        default_value_t = 0.25,
+        help = "Treat the scraper as broken when more than this share of entries can't be parsed"
+    )]
+    pub scraper_max_failure_ratio: f64,
+
+    #[arg(
+        long,
+        env = "METALPAL_SCRAPER_ALERT_CHANNEL",
+        help = "Slack channel to alert when the scraper looks broken"
+    )]
+    pub scraper_alert_channel: Option<String>,
+
     #[arg(long, env = "METALPAL_WHITELISTED_GENRE_KEYWORDS")]
     pub whitelisted_genre_keywords: Vec<String>,
 
     #[arg(long, env = "METALPAL_BLACKLISTED_GENRE_KEYWORDS")]
     pub blacklisted_genre_keywords: Vec<String>,
 
+    #[arg(
+        long = "follow",
+        env = "METALPAL_FOLLOWS",
+        help = "Artist whose releases are always posted, regardless of filters"
+    )]
+    pub follows: Vec<String>,
+
     #[arg(
         long,
         short,
@@ -58,6 +165,154 @@ pub struct CLI {
 
     #[arg(long, help = "Disable slack notifications")]
     pub disable_slack: bool,
+
+    #[arg(
+        long,
+        env = "METALPAL_REPORT",
+        value_name = "FILE",
+        help = "Write a JSON report with counters for the run to this file"
+    )]
+    pub report: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        short,
+        env = "METALPAL_OUTPUT",
+        value_enum,
+        default_value_t = OutputFormat::Table,
+        help = "Format for today's releases on stdout"
+    )]
+    pub output: OutputFormat,
+
+    #[arg(
+        long,
+        env = "METALPAL_INCLUDE_SKIPPED",
+        help = "Also output skipped releases along with their skip reasons"
+    )]
+    pub include_skipped: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+    Markdown,
+    Html,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage Slack digests that are scheduled for later delivery
+    Scheduled {
+        #[command(subcommand)]
+        action: ScheduledAction,
+    },
+
+    /// Run a Slack bot that answers mentions with today's releases
+    Bot {
+        /// Receive events over HTTP (Events API) on this address instead of using socket mode
+        #[arg(long, value_name = "ADDR")]
+        listen: Option<std::net::SocketAddr>,
+    },
+
+    /// Export interesting releases from the release store as an iCalendar (.ics) file
+    Calendar {
+        /// Write the calendar to this file instead of stdout
+        #[arg(long, short)]
+        file: Option<std::path::PathBuf>,
+
+        /// Serve the calendar over HTTP on this address so calendar apps can subscribe to it
+        #[arg(long, value_name = "ADDR", conflicts_with = "file")]
+        listen: Option<std::net::SocketAddr>,
+    },
+
+    /// Generate a static site of every release in the release store
+    Site {
+        /// Output directory; defaults to site_dir from the config
+        dir: Option<std::path::PathBuf>,
+    },
+
+    /// Serve the release store as a JSON API
+    Api {
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        listen: std::net::SocketAddr,
+    },
+
+    /// Browse and triage releases from the release store interactively
+    Tui,
+
+    /// Keep running, fetching, enriching and notifying on the schedule in `daemon`
+    Daemon {
+        /// Serve Prometheus metrics on this address (at /metrics)
+        #[arg(long, value_name = "ADDR", env = "METALPAL_METRICS_LISTEN")]
+        metrics_listen: Option<std::net::SocketAddr>,
+    },
+
+    /// Seed follows and artist affinity from your listening history
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+
+        /// Also follow artists that can't be found on Metal Archives
+        #[arg(long, global = true)]
+        keep_unmatched: bool,
+    },
+
+    /// Export interesting releases from the release store as an Atom feed
+    Feed {
+        /// Write the feed to this file instead of stdout
+        #[arg(long, short)]
+        file: Option<std::path::PathBuf>,
+
+        /// Serve the feed over HTTP on this address so feed readers can subscribe to it
+        #[arg(long, value_name = "ADDR", conflicts_with = "file")]
+        listen: Option<std::net::SocketAddr>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ImportSource {
+    /// Artists followed on Spotify; asks you to authorize access to your account
+    Spotify {
+        /// Redirect URI registered for the Spotify app; paste the URL it sends you to
+        #[arg(
+            long,
+            env = "METALPAL_SPOTIFY_REDIRECT_URI",
+            default_value = "http://localhost:8888/callback"
+        )]
+        redirect_uri: String,
+    },
+
+    /// Most played artists in a Last.fm export (JSON or CSV) or a .scrobbler.log
+    Lastfm {
+        file: std::path::PathBuf,
+
+        /// How many of the most played artists to import
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ScheduledAction {
+    /// List pending scheduled messages
+    List,
+
+    /// Cancel a pending scheduled message (or all of them)
+    Cancel {
+        #[arg(required_unless_present = "all", requires = "id")]
+        channel: Option<String>,
+
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+
+        #[arg(long, conflicts_with_all = ["channel", "id"])]
+        all: bool,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -67,10 +322,220 @@ pub struct Config {
     pub releases: Vec<Release>,
     pub slack_channels: Vec<String>,
     pub slack_bot_token: String,
+    #[serde(default)]
+    pub slack_app_token: String,
+    #[serde(default)]
+    pub slack_signing_secret: String,
+    #[serde(default)]
+    pub slack_delivery_time: Option<String>,
+    #[serde(default)]
+    pub slack_timezone: Option<String>,
+    #[serde(default)]
+    pub slack_join_channels: bool,
+    #[serde(default)]
+    pub slack_weekly_export: Option<String>,
+    #[serde(default)]
+    pub slack_last_weekly_export: Option<NaiveDate>,
+    #[serde(default)]
+    pub slack_upload_images: bool,
+    #[serde(default)]
+    pub slack_feedback: bool,
+    #[serde(default = "default_feedback_reactions")]
+    pub feedback_reactions: BTreeMap<String, i32>,
+    #[serde(default = "default_feedback_skip_threshold")]
+    pub feedback_skip_threshold: i32,
+    #[serde(default)]
+    pub notify_date_changes: bool,
+    #[serde(default)]
+    pub posted_messages: Vec<PostedMessage>,
+    #[serde(default)]
+    pub affinity: BTreeMap<String, Affinity>,
+    #[serde(default)]
+    pub notifiers: Vec<NotifierTarget>,
+    #[serde(default)]
+    pub site_dir: Option<String>,
+    #[serde(default = "default_fetch_interval_hours")]
+    pub fetch_interval_hours: u64,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    #[serde(default = "default_scraper_max_failure_ratio")]
+    pub scraper_max_failure_ratio: f64,
+    #[serde(default)]
+    pub scraper_alert_channel: Option<String>,
     pub spotify_client_id: String,
     pub spotify_client_secret: String,
     pub whitelisted_genre_keywords: Vec<String>,
     pub blacklisted_genre_keywords: Vec<String>,
+    #[serde(default)]
+    pub subscriptions: Vec<Subscription>,
+    #[serde(default)]
+    pub follows: Vec<Follow>,
+    /// The store as it was loaded, to merge in what others saved since
+    #[serde(skip)]
+    pub loaded: Option<serde_json::Value>,
+}
+
+/// Schedule for `metalpal daemon`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// How often today's releases are checked for ones that still need enriching
+    pub enrich_interval_minutes: u64,
+    /// Local times (HH:MM) at which today's releases are sent out
+    pub notify_times: Vec<String>,
+    /// Fetch and enrichment are delayed by up to this much so runs don't line up
+    pub jitter_seconds: u64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            enrich_interval_minutes: 30,
+            notify_times: vec!["09:00".to_string()],
+            jitter_seconds: 300,
+        }
+    }
+}
+
+/// A delivery target besides Slack; gets the releases matching `filter`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct NotifierTarget {
+    pub name: String,
+    #[serde(flatten)]
+    pub backend: NotifierBackend,
+    #[serde(default)]
+    pub filter: FilterProfile,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifierBackend {
+    /// Discord channel webhook; releases are sent as embeds
+    Discord { webhook_url: String },
+    /// Matrix room the access token's user has joined
+    Matrix {
+        homeserver: String,
+        access_token: String,
+        room_id: String,
+    },
+    /// HTML digest sent over SMTP
+    Email {
+        host: String,
+        #[serde(default)]
+        port: Option<u16>,
+        #[serde(default)]
+        security: SmtpSecurity,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+    /// Releases POSTed as JSON
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    #[default]
+    Starttls,
+    Tls,
+    /// Plain text; only meant for local test servers
+    None,
+}
+
+/// A release message posted to Slack; reactions on it feed into artist affinity
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PostedMessage {
+    pub channel: String,
+    /// Unknown for scheduled messages until they have been delivered
+    pub ts: Option<String>,
+    pub post_at: Option<i64>,
+    pub date: NaiveDate,
+    pub artist: String,
+    pub album: String,
+    pub genre: String,
+    pub label: String,
+    /// Score of the reactions already applied to `Config::affinity`
+    #[serde(default)]
+    pub score: i32,
+}
+
+/// An artist whose releases bypass the skip filters and are announced as soon as
+/// they show up; matched by any of the fields that are set
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Follow {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spotify_id: Option<String>,
+    /// The number at the end of the band's Metal Archives URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metallum_id: Option<String>,
+}
+
+/// How much the user likes an artist; genre and label are kept so that
+/// similar releases can be boosted or suppressed too
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Affinity {
+    pub score: i32,
+    #[serde(default)]
+    pub genre: String,
+    #[serde(default)]
+    pub label: String,
+}
+
+fn default_feedback_reactions() -> BTreeMap<String, i32> {
+    BTreeMap::from([("fire".to_string(), 1), ("-1".to_string(), -1)])
+}
+
+fn default_feedback_skip_threshold() -> i32 {
+    -3
+}
+
+fn default_fetch_interval_hours() -> u64 {
+    24
+}
+
+fn default_scraper_max_failure_ratio() -> f64 {
+    0.25
+}
+
+/// A channel or user that receives its own digest of releases matching `filter`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Subscription {
+    pub name: String,
+    pub target: SubscriptionTarget,
+    #[serde(default)]
+    pub filter: FilterProfile,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionTarget {
+    /// Slack channel name or ID
+    Channel(String),
+    /// Slack user ID; releases are delivered by DM
+    User(String),
+}
+
+/// Empty lists match everything; `artists` matches regardless of the other lists
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct FilterProfile {
+    #[serde(default)]
+    pub genres: Vec<String>,
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub artists: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -84,9 +549,59 @@ pub struct Release {
     pub skip_reasons: Vec<String>,
     pub spotify: Option<SpotifyArtistInfo>,
     pub metallum: Option<MetallumArtistInfo>,
+    // When enrichment last changed; feeds use it to tell readers about updated entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<chrono::DateTime<chrono::Utc>>,
+    // Set by hand in the TUI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub triage: Option<Triage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<ReleaseStatus>,
+    // Earlier dates, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub date_history: Vec<DateChange>,
+    // By an artist in `follows`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub followed: bool,
+    // Followed release that has been announced ahead of its date
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub announced: bool,
+    // Has been through enrichment, whether or not anything was found; followed
+    // releases aren't skipped, so this keeps them from being looked up again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub looked_up: bool,
+    // Sent to Slack and the notifiers on its release day, so later notify times
+    // (or runs) that day don't send it again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub notified: bool,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseStatus {
+    /// Moved to a later date
+    Postponed,
+    /// No longer on the release calendar before its date
+    Cancelled,
+}
+
+/// A date change noticed on a fetch
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct DateChange {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub seen: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Triage {
+    Skip,
+    Want,
+    Listened,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
 pub struct SpotifyArtistInfo {
     pub id: String,
     pub url: String,
@@ -95,7 +610,7 @@ pub struct SpotifyArtistInfo {
     pub followers: i64,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
 pub struct MetallumArtistInfo {
     pub name: String,
     pub url: String,
@@ -131,10 +646,33 @@ impl Default for Config {
             releases: vec![],
             slack_channels: vec![],
             slack_bot_token: "".to_string(),
+            slack_app_token: "".to_string(),
+            slack_signing_secret: "".to_string(),
+            slack_delivery_time: None,
+            slack_timezone: None,
+            slack_join_channels: false,
+            slack_weekly_export: None,
+            slack_last_weekly_export: None,
+            slack_upload_images: false,
+            slack_feedback: false,
+            feedback_reactions: default_feedback_reactions(),
+            feedback_skip_threshold: default_feedback_skip_threshold(),
+            notify_date_changes: false,
+            posted_messages: vec![],
+            affinity: BTreeMap::new(),
+            notifiers: vec![],
+            site_dir: None,
+            fetch_interval_hours: default_fetch_interval_hours(),
+            daemon: DaemonConfig::default(),
+            scraper_max_failure_ratio: default_scraper_max_failure_ratio(),
+            scraper_alert_channel: None,
             spotify_client_id: "".to_string(),
             spotify_client_secret: "".to_string(),
             whitelisted_genre_keywords: vec![],
             blacklisted_genre_keywords: vec![],
+            subscriptions: vec![],
+            follows: vec![],
+            loaded: None,
         }
     }
 }
@@ -144,9 +682,9 @@ pub fn full_path() -> Result<String, AppError> {
     let home_dir = match home_dir_opt {
         Some(home_dir) => home_dir.display().to_string(),
         None => {
-            return Err(AppError::GenericError {
-                0: "Could not find home directory".to_string(),
-            })
+            return Err(AppError::GenericError(
+                "Could not find home directory".to_string(),
+            ))
         }
     };
 
@@ -154,49 +692,109 @@ pub fn full_path() -> Result<String, AppError> {
 }
 
 pub fn load_config() -> Result<Config, AppError> {
-    // Try to get homedir
-    let full_path = full_path()?;
+    load_config_from(&full_path()?)
+}
 
+// The store is saved back to where it was loaded from
+pub fn load_config_from(full_path: &str) -> Result<Config, AppError> {
     // Try to lookup file
-    if !std::path::Path::new(&full_path).exists() {
-        return Err(AppError::GenericError {
-            0: format!("File '{}' does not exist", full_path.as_str()),
-        });
+    if !std::path::Path::new(full_path).exists() {
+        return Err(AppError::GenericError(format!(
+            "File '{}' does not exist",
+            full_path
+        )));
     }
 
     // Try to read + parse
     let contents = fs::read_to_string(full_path)?;
-    let config: Config = serde_json::from_str(contents.as_str())?;
+    let loaded: serde_json::Value = serde_json::from_str(contents.as_str())?;
+    let mut config: Config = serde_json::from_value(loaded.clone())?;
+    config.full_path = full_path.to_string();
+    config.loaded = Some(loaded);
+
+    Ok(config)
+}
+
+// Loads the store, changes it and saves it under the store lock, for quick
+// edits (ie. a triage mark) that mustn't overwrite anything saved in between
+pub fn update<F>(change: F) -> Result<Config, AppError>
+where
+    F: FnOnce(&mut Config) -> Result<(), AppError>,
+{
+    // Locked, loaded and written at the same path, which is also the config's
+    // full_path, so save_config() takes the same lock
+    let path = full_path()?;
+    let _lock = StoreLock::acquire(&path)?;
+
+    let mut config = load_config_from(&path)?;
+    change(&mut config)?;
+
+    let value = serde_json::to_value(&config)?;
+    write_store(&path, &value)?;
+    config.loaded = Some(value);
 
     Ok(config)
 }
 
 // Interactive setup
-pub fn setup_config(cli: &CLI) -> Result<Config, AppError> {
+pub fn setup_config(cli: &Cli) -> Result<Config, AppError> {
     if cli.interactive {
         return setup_interactive();
     }
 
-    return setup_cli(cli);
+    setup_cli(cli)
 }
 
-pub fn setup_cli(cli: &CLI) -> Result<Config, AppError> {
+pub fn setup_cli(cli: &Cli) -> Result<Config, AppError> {
     Ok(Config {
         full_path: cli.config_path.clone(),
         last_update: Default::default(),
         releases: vec![],
         slack_channels: cli.slack_channels.clone(),
         slack_bot_token: cli.slack_token.clone(),
+        slack_app_token: cli.slack_app_token.clone(),
+        slack_signing_secret: cli.slack_signing_secret.clone(),
+        slack_delivery_time: cli.slack_delivery_time.clone(),
+        slack_timezone: cli.slack_timezone.clone(),
+        slack_join_channels: cli.slack_join_channels,
+        slack_weekly_export: cli.slack_weekly_export.clone(),
+        slack_last_weekly_export: None,
+        slack_upload_images: cli.slack_upload_images,
+        slack_feedback: cli.slack_feedback,
+        feedback_reactions: default_feedback_reactions(),
+        feedback_skip_threshold: default_feedback_skip_threshold(),
+        notify_date_changes: cli.notify_date_changes,
+        posted_messages: vec![],
+        affinity: BTreeMap::new(),
+        notifiers: vec![],
+        site_dir: cli.site_dir.clone(),
+        fetch_interval_hours: cli.fetch_interval_hours,
+        daemon: DaemonConfig::default(),
+        scraper_max_failure_ratio: cli.scraper_max_failure_ratio,
+        scraper_alert_channel: cli.scraper_alert_channel.clone(),
         spotify_client_id: cli.spotify_client_id.clone(),
         spotify_client_secret: cli.spotify_client_secret.clone(),
         whitelisted_genre_keywords: cli.whitelisted_genre_keywords.clone(),
         blacklisted_genre_keywords: cli.blacklisted_genre_keywords.clone(),
+        subscriptions: vec![],
+        follows: cli
+            .follows
+            .iter()
+            .map(|name| Follow {
+                name: Some(name.clone()),
+                ..Default::default()
+            })
+            .collect(),
+        loaded: None,
     })
 }
 
 pub fn setup_interactive() -> Result<Config, AppError> {
     // Q: There are no zero values (or nil/null) - what is the idiomatic way to instantiate a struct with default values?
-    let mut config = Config::default();
+    let mut config = Config {
+        full_path: full_path()?,
+        ..Default::default()
+    };
 
     config.spotify_client_id = ask_question("Spotify client id (required): ", true)?;
     config.spotify_client_secret = ask_question("Spotify client secret (required): ", true)?;
@@ -206,6 +804,12 @@ pub fn setup_interactive() -> Result<Config, AppError> {
         "Slack channels (optional, comma separated; leave blank to skip): ",
         false,
     )?;
+    config.slack_delivery_time = ask_question_optional(
+        "Slack delivery time (optional, HH:MM; leave blank to post immediately): ",
+    )?;
+    config.slack_timezone = ask_question_optional(
+        "Slack delivery timezone (optional, ie. 'Europe/Berlin'; leave blank for system timezone): ",
+    )?;
     config.whitelisted_genre_keywords = ask_question_multi(
         "Whitelisted genre keywords (optional, comma separated; leave blank to skip): ",
         false,
@@ -215,20 +819,49 @@ pub fn setup_interactive() -> Result<Config, AppError> {
         false,
     )?;
 
-    config.full_path = full_path()?;
-
     Ok(config)
 }
 
-pub fn save_config(config: &Config) -> Result<(), AppError> {
-    let json_str = serde_json::to_string_pretty(&config)?;
-    fs::write(&config.full_path, json_str).map_err(|e| AppError::GenericError {
-        0: format!("Could not write config file '{}': {}", config.full_path, e),
-    })?;
+// Jobs save a store they loaded minutes ago, so whatever other commands saved
+// since (ie. a mark from the TUI) is merged in rather than overwritten; the
+// config is then updated to what was saved
+pub fn save_config(config: &mut Config) -> Result<(), AppError> {
+    let _lock = StoreLock::acquire(&config.full_path)?;
+
+    let ours = serde_json::to_value(&*config)?;
+    let theirs = match fs::read_to_string(&config.full_path) {
+        Ok(contents) => Some(serde_json::from_str(&contents)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let saved = match (&config.loaded, theirs) {
+        (Some(base), Some(theirs)) => merge::merge(base, &ours, &theirs),
+        _ => ours.clone(),
+    };
+
+    write_store(&config.full_path, &saved)?;
+
+    if saved != ours {
+        *config = serde_json::from_value(saved.clone())?;
+    }
+
+    config.loaded = Some(saved);
 
     Ok(())
 }
 
+// Written to a temporary file first so readers never see half a store
+fn write_store(path: &str, value: &serde_json::Value) -> Result<(), AppError> {
+    let tmp = format!("{}.tmp", path);
+
+    fs::write(&tmp, serde_json::to_string_pretty(value)?)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| {
+            AppError::GenericError(format!("Could not write config file '{}': {}", path, e))
+        })
+}
+
 fn ask_question_multi(prompt: &str, required: bool) -> Result<Vec<String>, AppError> {
     let answer = ask_question(prompt, required)?;
 
@@ -243,7 +876,17 @@ fn ask_question_multi(prompt: &str, required: bool) -> Result<Vec<String>, AppEr
     Ok(answer)
 }
 
-fn ask_question(prompt: &str, required: bool) -> Result<String, AppError> {
+fn ask_question_optional(prompt: &str) -> Result<Option<String>, AppError> {
+    let answer = ask_question(prompt, false)?;
+
+    if answer.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(answer))
+}
+
+pub fn ask_question(prompt: &str, required: bool) -> Result<String, AppError> {
     loop {
         print!("{}", prompt);
         io::stdout().flush()?; // Need to do this to ensure print! shows immediate output
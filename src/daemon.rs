@@ -0,0 +1,305 @@
+use crate::bot::shutdown_signal;
+use crate::config::{self, Config};
+use crate::lock::RunLock;
+use crate::pipeline::{self, Targets};
+use crate::{metrics, release, AppError};
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone};
+use log::{debug, error, info};
+use rand::Rng;
+use std::net::SocketAddr;
+
+#[derive(Debug, Clone, Copy)]
+enum Job {
+    Fetch,
+    Enrich,
+    Notify,
+}
+
+pub async fn run(
+    config: &Config,
+    disable_slack: bool,
+    metrics_listen: Option<SocketAddr>,
+) -> Result<(), AppError> {
+    let _lock = RunLock::acquire(&config.full_path)?;
+
+    // Jobs reload the store before they run; fail now rather than at every job
+    config::load_config_from(&config.full_path)?;
+
+    let notify_times = config
+        .daemon
+        .notify_times
+        .iter()
+        .map(|t| {
+            NaiveTime::parse_from_str(t, "%H:%M")
+                .map_err(|_| AppError::GenericError(format!("Invalid notify time '{}'", t)))
+        })
+        .collect::<Result<Vec<NaiveTime>, AppError>>()?;
+
+    let targets = Targets::setup(config, disable_slack).await?;
+
+    if let Some(addr) = metrics_listen {
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(addr).await {
+                error!("Metrics server failed: {}", e);
+            }
+        });
+    }
+
+    let now = Local::now();
+
+    // Fetch right away if the release list is outdated
+    let mut next_fetch = if release::out_of_date(config) {
+        now
+    } else {
+        config.last_update.with_timezone(&Local) + fetch_interval(config)
+    };
+    let mut next_enrich = now;
+    // A notify time that passed while the daemon wasn't running is caught up on;
+    // releases that were already sent aren't sent again
+    let mut next_notify = if missed_notify(&notify_times, now) {
+        Some(now)
+    } else {
+        next_notify_time(&notify_times, now)
+    };
+
+    info!(
+        "Running as daemon; fetching every {}h, enriching every {}m, notifying at {}",
+        config.fetch_interval_hours,
+        config.daemon.enrich_interval_minutes,
+        config.daemon.notify_times.join(", ")
+    );
+
+    // Only alert once per breakage; fetches are retried every enrich interval
+    let mut scraper_alerted = false;
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        let mut jobs = vec![(Job::Fetch, next_fetch), (Job::Enrich, next_enrich)];
+
+        if let Some(next_notify) = next_notify {
+            jobs.push((Job::Notify, next_notify));
+        }
+
+        let (job, at) = jobs
+            .into_iter()
+            .min_by_key(|(_, at)| *at)
+            .unwrap_or((Job::Enrich, next_enrich));
+
+        debug!("Next job is {:?} at {}", job, at);
+
+        let wait = (at - Local::now()).to_std().unwrap_or_default();
+
+        tokio::select! {
+            _ = &mut shutdown => {
+                info!("Stopping daemon");
+                return Ok(());
+            }
+            _ = tokio::time::sleep(wait) => {}
+        }
+
+        // Jobs run one at a time and aren't interrupted by a shutdown, so the release
+        // store is always saved in a consistent state. Each job works on a fresh copy
+        // of the store; whatever other commands (ie. the TUI) save while it runs is
+        // merged in when the job saves. The store is the one the daemon was
+        // started with.
+        let result = match config::load_config_from(&config.full_path) {
+            Ok(mut config) => match job {
+                Job::Fetch => fetch(&mut config, &targets).await,
+                Job::Enrich => enrich_pending(&mut config).await,
+                Job::Notify => notify(&mut config, &targets).await,
+            },
+            Err(e) => Err(e),
+        };
+
+        let job_name = format!("{:?}", job).to_lowercase();
+
+        match &result {
+            Ok(()) => metrics::inc(metrics::JOBS, &[("job", &job_name), ("result", "ok")]),
+            Err(e) => {
+                error!("{:?} failed: {}", job, e);
+                metrics::inc(metrics::JOBS, &[("job", &job_name), ("result", "error")]);
+            }
+        }
+
+        match (job, &result) {
+            (Job::Fetch, Err(e @ AppError::ScraperError(_))) if !scraper_alerted => {
+                targets.alert_scraper(config, e).await;
+                scraper_alerted = true;
+            }
+            (Job::Fetch, Ok(())) => scraper_alerted = false,
+            _ => {}
+        }
+
+        // The next run is scheduled from when the job finished, so a slow job
+        // delays its next run rather than overlapping with it
+        let now = Local::now();
+
+        match (job, result) {
+            // Retry a failed fetch sooner than a day later
+            (Job::Fetch, Err(_)) => next_fetch = now + enrich_interval(config),
+            (Job::Fetch, Ok(())) => next_fetch = now + fetch_interval(config),
+            (Job::Enrich, _) => next_enrich = now + enrich_interval(config),
+            (Job::Notify, _) => next_notify = next_notify_time(&notify_times, now),
+        }
+    }
+}
+
+async fn fetch(config: &mut Config, targets: &Targets) -> Result<(), AppError> {
+    let notices = pipeline::fetch(config).await?;
+
+    targets.announce(&notices).await
+}
+
+// Today's releases that haven't been looked up yet, ie. ones that showed up since
+// the last fetch
+async fn enrich_pending(config: &mut Config) -> Result<(), AppError> {
+    let mut pending: Vec<_> = release::get_releases_today(&config.releases)
+        .into_iter()
+        .filter(|r| !r.skip && !r.looked_up)
+        .collect();
+
+    if pending.is_empty() {
+        debug!("No releases to enrich");
+        return Ok(());
+    }
+
+    pipeline::enrich(config, &mut pending).await
+}
+
+async fn notify(config: &mut Config, targets: &Targets) -> Result<(), AppError> {
+    enrich_pending(config).await?;
+
+    targets.collect_feedback(config).await?;
+    targets.weekly_export(config).await?;
+
+    // Only enriched releases can be ranked; followed ones are posted regardless
+    let releases_today: Vec<_> = release::get_releases_today(&config.releases)
+        .into_iter()
+        .filter(|r| r.followed || (r.spotify.is_some() && r.metallum.is_some()))
+        .collect();
+
+    let valid_releases = release::filter_valid_releases(config, &releases_today);
+
+    if valid_releases.is_empty() {
+        info!("No interesting releases today");
+        return Ok(());
+    }
+
+    targets.notify(config, &valid_releases).await
+}
+
+// Times that don't exist on a day (skipped by a DST change) are skipped; ones
+// that happen twice are taken the first time
+fn next_notify_time<Tz: TimeZone>(times: &[NaiveTime], now: DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let today = now.date_naive();
+    let timezone = now.timezone();
+
+    [today, today + Duration::days(1)]
+        .iter()
+        .flat_map(|date| times.iter().map(move |time| date.and_time(*time)))
+        .filter_map(|dt| timezone.from_local_datetime(&dt).earliest())
+        .filter(|dt| *dt > now)
+        .min()
+}
+
+// Whether one of today's notify times has already passed
+fn missed_notify<Tz: TimeZone>(times: &[NaiveTime], now: DateTime<Tz>) -> bool {
+    let timezone = now.timezone();
+
+    times
+        .iter()
+        .filter_map(|time| {
+            timezone
+                .from_local_datetime(&now.date_naive().and_time(*time))
+                .earliest()
+        })
+        .any(|dt| dt <= now)
+}
+
+fn fetch_interval(config: &Config) -> Duration {
+    Duration::hours(config.fetch_interval_hours as i64) + jitter(config)
+}
+
+fn enrich_interval(config: &Config) -> Duration {
+    Duration::minutes(config.daemon.enrich_interval_minutes as i64) + jitter(config)
+}
+
+// Spreads out requests to Spotify and Metal Archives
+fn jitter(config: &Config) -> Duration {
+    let max = config.daemon.jitter_seconds as i64;
+
+    if max == 0 {
+        return Duration::zero();
+    }
+
+    Duration::seconds(rand::thread_rng().gen_range(0..=max))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono_tz::Europe::Berlin;
+
+    fn times(times: &[&str]) -> Vec<NaiveTime> {
+        times
+            .iter()
+            .map(|t| NaiveTime::parse_from_str(t, "%H:%M").unwrap())
+            .collect()
+    }
+
+    fn berlin(s: &str) -> DateTime<chrono_tz::Tz> {
+        let dt = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        Berlin.from_local_datetime(&dt).earliest().unwrap()
+    }
+
+    #[test]
+    fn next_notify_today_or_tomorrow() {
+        let times = times(&["09:00", "18:00"]);
+
+        assert_eq!(
+            next_notify_time(&times, berlin("2023-06-01 08:00")),
+            Some(berlin("2023-06-01 09:00"))
+        );
+        assert_eq!(
+            next_notify_time(&times, berlin("2023-06-01 09:00")),
+            Some(berlin("2023-06-01 18:00"))
+        );
+        assert_eq!(
+            next_notify_time(&times, berlin("2023-06-01 20:00")),
+            Some(berlin("2023-06-02 09:00"))
+        );
+        assert_eq!(next_notify_time(&[], berlin("2023-06-01 20:00")), None);
+    }
+
+    #[test]
+    fn missed_notify_today() {
+        let times = times(&["09:00", "18:00"]);
+
+        assert!(!missed_notify(&times, berlin("2023-06-01 08:00")));
+        assert!(missed_notify(&times, berlin("2023-06-01 09:00")));
+        assert!(missed_notify(&times, berlin("2023-06-01 20:00")));
+        assert!(!missed_notify(&[], berlin("2023-06-01 20:00")));
+    }
+
+    #[test]
+    fn next_notify_across_dst() {
+        // 02:30 doesn't exist on March 26, 2023 in Berlin
+        let times = times(&["02:30"]);
+
+        assert_eq!(
+            next_notify_time(&times, berlin("2023-03-26 01:00")),
+            Some(berlin("2023-03-27 02:30"))
+        );
+
+        // ...and happens twice on October 29; the first one is taken
+        let first = next_notify_time(&times, berlin("2023-10-29 01:00")).unwrap();
+        assert_eq!(first, berlin("2023-10-29 02:30"));
+        assert_eq!(first.to_rfc3339(), "2023-10-29T02:30:00+02:00");
+
+        // A day ahead in wall clock time, which is 25 hours
+        let next = next_notify_time(&times, first).unwrap();
+        assert_eq!(next.to_rfc3339(), "2023-10-30T02:30:00+01:00");
+    }
+}
@@ -1,35 +1,41 @@
-use crate::config::Release;
+use crate::config::{OutputFormat, Release};
+use crate::error::AppError;
+use crate::export;
+use chrono::{Local, TimeZone};
 use log::info;
 use prettytable::{Cell, Row, Table};
+use slack_rust::chat::scheduled_messages_list::ScheduledMessage;
 
-pub fn display(valid_releases: &Vec<&Release>, releases_today: &Vec<Release>) {
+pub fn display(valid_releases: &[&Release], releases_today: &[Release]) {
     info!(
         "There are '{}' releases today; out of those, '{}' look interesting!\n",
         releases_today.len(),
         valid_releases.len(),
     );
 
-    let mut iter = 1;
-
     // Display release in tables, sorted by follower count
-    for release in valid_releases {
+    for (i, release) in valid_releases.iter().enumerate() {
         let mut table = Table::new();
 
         // Header
-        let mut header = format!("{}. {} - {}", iter, release.artist, release.album);
+        let mut header = format!("{}. {} - {}", i + 1, release.artist, release.album);
+
+        // Followed releases may not have been enriched
+        let spotify_metadata = release.spotify.clone().unwrap_or_default();
+        let metallum_metadata = release.metallum.clone().unwrap_or_default();
 
-        if release.spotify.clone().unwrap().followers > 100_000 {
+        if spotify_metadata.followers > 100_000 {
             header = "🔥 ".to_string() + header.as_str() + " 🔥";
         }
 
+        if release.followed {
+            header = "⭐ ".to_string() + header.as_str() + " (followed)";
+        }
+
         table.set_titles(Row::new(vec![
             Cell::new(header.as_str()).style_spec("bFgcH2")
         ]));
 
-        // unwrap()'s are fine because we non-skipped entries have both
-        let spotify_metadata = release.spotify.clone().unwrap();
-        let metallum_metadata = release.metallum.clone().unwrap();
-
         table.add_row(Row::new(vec![
             Cell::new("Metallum URL"),
             Cell::new(metallum_metadata.url.as_str()),
@@ -66,7 +72,96 @@ pub fn display(valid_releases: &Vec<&Release>, releases_today: &Vec<Release>) {
         ]));
 
         table.printstd();
+    }
+}
+
+fn display_skipped(releases_today: &[Release]) {
+    let mut table = Table::new();
+
+    table.set_titles(Row::new(vec![
+        Cell::new("Skipped").style_spec("bFy"),
+        Cell::new("Reasons").style_spec("bFy"),
+    ]));
+
+    for release in releases_today.iter().filter(|r| r.skip) {
+        table.add_row(Row::new(vec![
+            Cell::new(format!("{} - {}", release.artist, release.album).as_str()),
+            Cell::new(release.skip_reasons.join("\n").as_str()),
+        ]));
+    }
+
+    table.printstd();
+}
+
+// Prints today's releases in the requested format; skipped releases follow the
+// valid ones when include_skipped is set
+pub fn output(
+    format: OutputFormat,
+    valid_releases: &[&Release],
+    releases_today: &[Release],
+    include_skipped: bool,
+) -> Result<(), AppError> {
+    let mut releases = valid_releases.to_vec();
+
+    if include_skipped {
+        releases.extend(releases_today.iter().filter(|r| r.skip));
+    }
 
-        iter += 1;
+    let title = format!(
+        "metalpal releases for {}",
+        chrono::Local::now().date_naive()
+    );
+
+    let out = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(&releases)? + "\n",
+        OutputFormat::Csv => export::csv_all(&releases),
+        OutputFormat::Markdown => export::markdown(&releases),
+        OutputFormat::Html => export::html_all(&title, &releases),
+        OutputFormat::Table => {
+            display(valid_releases, releases_today);
+
+            if include_skipped {
+                display_skipped(releases_today);
+            }
+
+            return Ok(());
+        }
+    };
+
+    print!("{}", out);
+
+    Ok(())
+}
+
+pub fn display_scheduled(messages: &[ScheduledMessage]) {
+    if messages.is_empty() {
+        info!("There are no pending scheduled messages");
+        return;
     }
+
+    let mut table = Table::new();
+
+    table.set_titles(Row::new(vec![
+        Cell::new("ID").style_spec("bFg"),
+        Cell::new("Channel").style_spec("bFg"),
+        Cell::new("Post At").style_spec("bFg"),
+        Cell::new("Text").style_spec("bFg"),
+    ]));
+
+    for m in messages {
+        let post_at = m
+            .post_at
+            .and_then(|ts| Local.timestamp_opt(ts as i64, 0).single())
+            .map(|dt| dt.to_string())
+            .unwrap_or_default();
+
+        table.add_row(Row::new(vec![
+            Cell::new(m.id.as_deref().unwrap_or_default()),
+            Cell::new(m.channel_id.as_deref().unwrap_or_default()),
+            Cell::new(post_at.as_str()),
+            Cell::new(m.text.as_deref().unwrap_or_default()),
+        ]));
+    }
+
+    table.printstd();
 }
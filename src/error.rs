@@ -1,6 +1,7 @@
 use scraper::error::SelectorErrorKind;
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("Error: {0}")]
@@ -30,12 +31,6 @@ pub enum AppError {
         source: SelectorErrorKind<'static>,
     },
 
-    #[error("Parse error: {source}")]
-    ParseError {
-        #[from]
-        source: regex::Error,
-    },
-
     #[error("Date error: {source}")]
     DateError {
         #[from]
@@ -48,8 +43,21 @@ pub enum AppError {
         source: rspotify::ClientError,
     },
 
+    #[error("Server error: {source}")]
+    ServerError {
+        #[from]
+        source: hyper::Error,
+    },
+
     #[error("Slack Error: {0}")]
     SlackError(String),
+
+    #[error("Notifier error: {0}")]
+    NotifierError(String),
+
+    // The release page was fetched but doesn't look like it used to
+    #[error("Scraper broken: {0}")]
+    ScraperError(String),
 }
 
 // slack_rust does not implement the std::error::Error trait, so we have to do this manually
@@ -0,0 +1,529 @@
+use crate::config::Release;
+use crate::release;
+
+// Every field of a release and its Spotify/Metallum metadata, flattened
+const ALL_FIELDS: [&str; 29] = [
+    "date",
+    "artist",
+    "album",
+    "label",
+    "skip",
+    "skip_reasons",
+    "updated",
+    "triage",
+    "status",
+    "previous_dates",
+    "spotify_id",
+    "spotify_url",
+    "spotify_genres",
+    "spotify_popularity",
+    "spotify_followers",
+    "metallum_name",
+    "metallum_url",
+    "metallum_description_short",
+    "metallum_description_long",
+    "metallum_country_origin",
+    "metallum_locations",
+    "metallum_years_active",
+    "metallum_formed_in",
+    "metallum_genre",
+    "metallum_themes",
+    "metallum_status",
+    "metallum_last_label",
+    "metallum_band_img_url",
+    "metallum_band_name_img_url",
+];
+
+const CSV_HEADER: [&str; 8] = [
+    "date",
+    "artist",
+    "album",
+    "label",
+    "genre",
+    "country",
+    "metallum_url",
+    "spotify_url",
+];
+
+// One row per release; enrichment columns are empty for unenriched releases
+pub fn csv(releases: &[&Release]) -> String {
+    let mut out = CSV_HEADER.join(",") + "\r\n";
+
+    for release in releases {
+        let row = [
+            release.date.to_string(),
+            release.artist.clone(),
+            release.album.clone(),
+            release.label.clone(),
+            release
+                .metallum
+                .as_ref()
+                .map(|m| m.genre.clone())
+                .unwrap_or_default(),
+            release
+                .metallum
+                .as_ref()
+                .map(|m| m.country_origin.clone())
+                .unwrap_or_default(),
+            release
+                .metallum
+                .as_ref()
+                .map(|m| m.url.clone())
+                .unwrap_or_default(),
+            release
+                .spotify
+                .as_ref()
+                .map(|s| s.url.clone())
+                .unwrap_or_default(),
+        ];
+
+        out += &csv_row(&row);
+    }
+
+    out
+}
+
+// Like csv() but with every stored field, including skipped releases' reasons
+pub fn csv_all(releases: &[&Release]) -> String {
+    let mut out = csv_row(&ALL_FIELDS);
+
+    for release in releases {
+        out += &csv_row(&all_fields(release));
+    }
+
+    out
+}
+
+fn csv_row<S: AsRef<str>>(row: &[S]) -> String {
+    let row: Vec<String> = row.iter().map(|field| csv_field(field.as_ref())).collect();
+    row.join(",") + "\r\n"
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// An all-day event per release; releases that haven't been enriched yet are
+// tentative
+pub fn ics(releases: &[&Release]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//metalpal//metalpal//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    for release in releases {
+        let date = release.date.format("%Y%m%d").to_string();
+        let end = release
+            .date
+            .succ_opt()
+            .unwrap_or(release.date)
+            .format("%Y%m%d")
+            .to_string();
+
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}@metalpal", release::id(release)));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", date));
+        lines.push(format!("DTEND;VALUE=DATE:{}", end));
+        lines.push(format!(
+            "SUMMARY:{}",
+            ics_text(&format!("{} – {}", release.artist, release.album))
+        ));
+        let enriched = release.spotify.is_some() && release.metallum.is_some();
+        let mut description = description(release);
+
+        if !enriched {
+            lines.push("STATUS:TENTATIVE".to_string());
+            description
+                .push("Not filtered yet; metalpal looks it up on the release day".to_string());
+        }
+
+        lines.push(format!("DESCRIPTION:{}", ics_text(&description.join("\n"))));
+
+        if let Some(metallum) = &release.metallum {
+            lines.push(format!("URL:{}", metallum.url));
+        }
+
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold(line))
+        .collect::<Vec<String>>()
+        .join("\r\n")
+        + "\r\n"
+}
+
+// Lines describing a release in calendar events and feed entries
+fn description(release: &Release) -> Vec<String> {
+    let mut description = vec![format!("Label: {}", release.label)];
+
+    if let Some(metallum) = &release.metallum {
+        description.push(format!("Genre: {}", metallum.genre));
+        description.push(format!("Country: {}", metallum.country_origin));
+        description.push(format!("Metal Archives: {}", metallum.url));
+    }
+
+    if let Some(spotify) = &release.spotify {
+        description.push(format!("Spotify: {}", spotify.url));
+    }
+
+    description
+}
+
+fn ics_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 octets are folded onto continuation lines starting with a space
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut len = 0;
+
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            folded += "\r\n ";
+            len = 1;
+        }
+
+        folded.push(c);
+        len += c.len_utf8();
+    }
+
+    folded
+}
+
+// A self-contained HTML page with a table of releases
+pub fn html(title: &str, releases: &[&Release]) -> String {
+    let mut rows = String::new();
+
+    for release in releases {
+        let (genre, country, url) = match &release.metallum {
+            Some(m) => (m.genre.as_str(), m.country_origin.as_str(), m.url.as_str()),
+            None => ("", "", ""),
+        };
+
+        let name = format!("{} - {}", release.artist, release.album);
+        let name = if url.is_empty() {
+            html_escape(&name)
+        } else {
+            format!(
+                "<a href=\"{}\">{}</a>",
+                html_escape(url),
+                html_escape(&name)
+            )
+        };
+
+        rows += &format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            release.date,
+            name,
+            html_escape(genre),
+            html_escape(country),
+            html_escape(&release.label)
+        );
+    }
+
+    format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+         <body>\n\
+         <h1>{title}</h1>\n\
+         <table>\n\
+         <tr><th>Date</th><th>Release</th><th>Genre</th><th>Country</th><th>Label</th></tr>\n\
+         {rows}\
+         </table>\n\
+         </body>\n\
+         </html>\n",
+        title = html_escape(title),
+        rows = rows
+    )
+}
+
+// An Atom feed with an entry per release; entries without enrichment updates
+// are dated on their release day
+pub fn atom(title: &str, releases: &[&Release]) -> String {
+    let updated = |release: &Release| {
+        release.updated.unwrap_or_else(|| {
+            release
+                .date
+                .and_hms_opt(0, 0, 0)
+                .unwrap_or_default()
+                .and_utc()
+        })
+    };
+
+    let feed_updated = releases
+        .iter()
+        .map(|r| updated(r))
+        .max()
+        .unwrap_or_else(chrono::Utc::now);
+
+    let mut entries = String::new();
+
+    for release in releases {
+        let content: Vec<String> = description(release)
+            .iter()
+            .map(|line| html_escape(line))
+            .collect();
+
+        entries += "<entry>\n";
+        entries += &format!("<id>urn:metalpal:release:{}</id>\n", release::id(release));
+        entries += &format!(
+            "<title>{}</title>\n",
+            html_escape(&format!("{} – {}", release.artist, release.album))
+        );
+        entries += &format!("<updated>{}</updated>\n", updated(release).to_rfc3339());
+        entries += &format!("<published>{}T00:00:00Z</published>\n", release.date);
+
+        if let Some(metallum) = &release.metallum {
+            entries += &format!("<link href=\"{}\"/>\n", html_escape(&metallum.url));
+            entries += &format!("<category term=\"{}\"/>\n", html_escape(&metallum.genre));
+        }
+
+        // The HTML content is escaped once more to embed it in the XML
+        entries += &format!(
+            "<content type=\"html\">{}</content>\n",
+            html_escape(&content.join("<br>"))
+        );
+        entries += "</entry>\n";
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <id>urn:metalpal:releases</id>\n\
+         <title>{title}</title>\n\
+         <updated>{updated}</updated>\n\
+         <author><name>metalpal</name></author>\n\
+         {entries}\
+         </feed>\n",
+        title = html_escape(title),
+        updated = feed_updated.to_rfc3339(),
+        entries = entries
+    )
+}
+
+pub fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// Values in ALL_FIELDS order; missing metadata becomes empty values
+fn all_fields(release: &Release) -> Vec<String> {
+    let mut fields = vec![
+        release.date.to_string(),
+        release.artist.clone(),
+        release.album.clone(),
+        release.label.clone(),
+        release.skip.to_string(),
+        release.skip_reasons.join("; "),
+        release.updated.map(|u| u.to_rfc3339()).unwrap_or_default(),
+        release
+            .triage
+            .map(|t| format!("{:?}", t).to_lowercase())
+            .unwrap_or_default(),
+        release
+            .status
+            .map(|s| format!("{:?}", s).to_lowercase())
+            .unwrap_or_default(),
+        release
+            .date_history
+            .iter()
+            .map(|c| c.from.to_string())
+            .collect::<Vec<String>>()
+            .join("; "),
+    ];
+
+    match &release.spotify {
+        Some(s) => fields.extend([
+            s.id.clone(),
+            s.url.clone(),
+            s.genres.join(", "),
+            s.popularity.to_string(),
+            s.followers.to_string(),
+        ]),
+        None => fields.extend(vec![String::new(); 5]),
+    }
+
+    match &release.metallum {
+        Some(m) => fields.extend([
+            m.name.clone(),
+            m.url.clone(),
+            m.description_short.clone(),
+            m.description_long.clone(),
+            m.country_origin.clone(),
+            m.locations.clone(),
+            m.years_active.clone(),
+            m.formed_in.clone(),
+            m.genre.clone(),
+            m.themes.clone(),
+            m.status.clone(),
+            m.last_label.clone(),
+            m.band_img_url.clone(),
+            m.band_name_img_url.clone(),
+        ]),
+        None => fields.extend(vec![String::new(); 14]),
+    }
+
+    fields
+}
+
+// A GitHub-flavoured Markdown table with every field
+pub fn markdown(releases: &[&Release]) -> String {
+    let mut out = format!("| {} |\n", ALL_FIELDS.join(" | "));
+    out += &format!("|{}\n", " --- |".repeat(ALL_FIELDS.len()));
+
+    for release in releases {
+        let row: Vec<String> = all_fields(release)
+            .iter()
+            .map(|field| {
+                field
+                    .replace('|', "\\|")
+                    .replace("\r\n", "<br>")
+                    .replace(['\r', '\n'], "<br>")
+            })
+            .collect();
+
+        out += &format!("| {} |\n", row.join(" | "));
+    }
+
+    out
+}
+
+// Like html() but with every field as a column
+pub fn html_all(title: &str, releases: &[&Release]) -> String {
+    let header: String = ALL_FIELDS
+        .iter()
+        .map(|field| format!("<th>{}</th>", field))
+        .collect();
+
+    let mut rows = String::new();
+
+    for release in releases {
+        let row: String = all_fields(release)
+            .iter()
+            .map(|field| format!("<td>{}</td>", html_escape(field)))
+            .collect();
+
+        rows += &format!("<tr>{}</tr>\n", row);
+    }
+
+    format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+         <body>\n\
+         <h1>{title}</h1>\n\
+         <table>\n\
+         <tr>{header}</tr>\n\
+         {rows}\
+         </table>\n\
+         </body>\n\
+         </html>\n",
+        title = html_escape(title),
+        header = header,
+        rows = rows
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{MetallumArtistInfo, SpotifyArtistInfo};
+    use chrono::NaiveDate;
+
+    fn release(enriched: bool) -> Release {
+        Release {
+            date: NaiveDate::from_ymd_opt(2023, 3, 10).unwrap(),
+            artist: "Enslaved".to_string(),
+            album: "Heimdal".to_string(),
+            label: "Nuclear Blast".to_string(),
+            skip: false,
+            skip_reasons: vec![],
+            spotify: enriched.then(SpotifyArtistInfo::default),
+            metallum: enriched.then(MetallumArtistInfo::default),
+            updated: None,
+            triage: None,
+            status: None,
+            date_history: vec![],
+            followed: false,
+            announced: false,
+            looked_up: false,
+            notified: false,
+        }
+    }
+
+    #[test]
+    fn all_fields_match_header() {
+        assert_eq!(all_fields(&release(true)).len(), ALL_FIELDS.len());
+        assert_eq!(all_fields(&release(false)).len(), ALL_FIELDS.len());
+    }
+
+    #[test]
+    fn csv_escaping() {
+        assert_eq!(csv_field("Heimdal"), "Heimdal");
+        assert_eq!(csv_field("Sons, Daughters"), "\"Sons, Daughters\"");
+        assert_eq!(csv_field("The \"Best\" Of"), "\"The \"\"Best\"\" Of\"");
+        assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+
+        let mut release = release(false);
+        release.album = "Heimdal, \"Deluxe\"".to_string();
+
+        let csv = csv_all(&[&release]);
+        let rows: Vec<&str> = csv.split("\r\n").collect();
+
+        assert_eq!(rows[0], ALL_FIELDS.join(","));
+        assert!(
+            rows[1].starts_with("2023-03-10,Enslaved,\"Heimdal, \"\"Deluxe\"\"\",Nuclear Blast,")
+        );
+    }
+
+    #[test]
+    fn markdown_escaping() {
+        let mut release = release(false);
+        release.album = "Either | Or".to_string();
+        release.label = "Line\r\nbreak\rand\nmore".to_string();
+
+        let markdown = markdown(&[&release]);
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        // A header, a separator and one row
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].starts_with(
+            "| 2023-03-10 | Enslaved | Either \\| Or | Line<br>break<br>and<br>more |"
+        ));
+    }
+
+    #[test]
+    fn html_escaping() {
+        let mut release = release(false);
+        release.artist = "<script>".to_string();
+        release.album = "Rock & \"Roll\" 'n'".to_string();
+
+        let html = html_all("Tom & Jerry", &[&release]);
+
+        assert!(html.contains("<title>Tom &amp; Jerry</title>"));
+        assert!(html
+            .contains("<td>&lt;script&gt;</td><td>Rock &amp; &quot;Roll&quot; &#39;n&#39;</td>"));
+        assert!(!html.contains("<script>"));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::config::Config;
+use crate::server::Document;
+use crate::{export, release};
+
+// Feed readers only look at recent entries
+const MAX_ENTRIES: usize = 100;
+
+pub const DOCUMENT: Document = Document {
+    path: "/metalpal.atom",
+    content_type: "application/atom+xml; charset=utf-8",
+    render: atom,
+};
+
+// The most recent releases that made it through filtering, newest first
+pub fn atom(config: &Config) -> String {
+    let releases: Vec<_> = release::stored_valid_releases(config)
+        .into_iter()
+        .rev()
+        .take(MAX_ENTRIES)
+        .collect();
+
+    export::atom("metalpal releases", &releases)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{MetallumArtistInfo, Release, SpotifyArtistInfo};
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    fn release(date: &str, artist: &str) -> Release {
+        serde_json::from_value(serde_json::json!({
+            "date": date,
+            "artist": artist,
+            "album": "Album",
+            "label": "",
+            "skip_reasons": [],
+            "spotify": SpotifyArtistInfo::default(),
+            "metallum": MetallumArtistInfo {
+                genre: "Black Metal".to_string(),
+                url: "https://www.metal-archives.com/bands/Enslaved/159".to_string(),
+                ..Default::default()
+            },
+        }))
+        .unwrap()
+    }
+
+    fn ids(feed: &str) -> Vec<&str> {
+        feed.split("<id>urn:metalpal:release:")
+            .skip(1)
+            .filter_map(|entry| entry.split("</id>").next())
+            .collect()
+    }
+
+    #[test]
+    fn valid_releases_newest_first() {
+        let mut skipped = release("2023-04-21", "Nickelback");
+        skipped.skip = true;
+
+        let mut unenriched = release("2023-04-21", "Gojira");
+        unenriched.metallum = None;
+
+        let config = Config {
+            releases: vec![
+                release("2023-04-07", "Opeth"),
+                release("2023-04-14", "Enslaved"),
+                skipped,
+                unenriched,
+            ],
+            ..Default::default()
+        };
+
+        let feed = atom(&config);
+
+        assert_eq!(ids(&feed), vec!["enslaved-album", "opeth-album"]);
+        assert!(feed.contains("<category term=\"Black Metal\"/>"));
+        // Without enrichment updates, the feed is as new as its newest release
+        assert!(feed.contains("<updated>2023-04-14T00:00:00+00:00</updated>\n<author>"));
+    }
+
+    #[test]
+    fn entries_keep_their_id() {
+        let mut moved = release("2023-05-05", "Enslaved");
+        moved.updated = Some(Utc.with_ymd_and_hms(2023, 4, 20, 12, 0, 0).unwrap());
+
+        let before = atom(&Config {
+            releases: vec![release("2023-04-14", "Enslaved")],
+            ..Default::default()
+        });
+        let after = atom(&Config {
+            releases: vec![moved],
+            ..Default::default()
+        });
+
+        // A postponed release is the same entry, updated
+        assert_eq!(ids(&before), ids(&after));
+        assert!(after.contains("<updated>2023-04-20T12:00:00+00:00</updated>"));
+        assert!(after.contains("<published>2023-05-05T00:00:00Z</published>"));
+    }
+
+    #[test]
+    fn entries_are_escaped_and_capped() {
+        let start = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+
+        let mut releases: Vec<Release> = (0..MAX_ENTRIES + 10)
+            .map(|i| {
+                let date = start + chrono::Duration::days(i as i64);
+                release(&date.to_string(), &format!("Band {}", i))
+            })
+            .collect();
+        releases[MAX_ENTRIES + 9].artist = "Dälek & <Friends>".to_string();
+
+        let feed = atom(&Config {
+            releases,
+            ..Default::default()
+        });
+
+        assert_eq!(ids(&feed).len(), MAX_ENTRIES);
+        assert!(feed.contains("<title>Dälek &amp; &lt;Friends&gt; – Album</title>"));
+        // The oldest are left out
+        assert!(!feed.contains("Band 0 "));
+    }
+}
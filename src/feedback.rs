@@ -0,0 +1,315 @@
+use crate::config::{Affinity, Config, PostedMessage, Release};
+use crate::slack::Slack;
+use crate::{normalize, release, AppError};
+use chrono::{Duration, Local};
+use log::{debug, info};
+use std::collections::BTreeMap;
+
+// Reactions on messages older than this are no longer checked
+const FEEDBACK_DAYS: i64 = 14;
+
+// How much an artist's affinity counts towards a release by the same artist,
+// compared to one that only shares a genre or the label
+const ARTIST_WEIGHT: i32 = 3;
+
+// Reads reactions on previously posted release messages and applies any
+// change in their score to the affinity of the release's artist
+pub async fn collect(config: &mut Config, slack: &Slack) -> Result<(), AppError> {
+    let oldest = Local::now().date_naive() - Duration::days(FEEDBACK_DAYS);
+
+    config.posted_messages.retain(|m| m.date >= oldest);
+
+    let mut reactions = vec![];
+
+    for message in &mut config.posted_messages {
+        reactions.push(slack.message_reactions(message).await?);
+    }
+
+    learn(config, &reactions);
+
+    Ok(())
+}
+
+// Scores each posted message from its reactions (None if they couldn't be read
+// yet). A release posted to several channels counts once, with the score of
+// its most strongly reacted to message
+fn learn(config: &mut Config, reactions: &[Option<Vec<(String, i32)>>]) {
+    let mut by_release: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+    for (i, message) in config.posted_messages.iter().enumerate() {
+        by_release
+            .entry(release::id_of(&message.artist, &message.album))
+            .or_default()
+            .push(i);
+    }
+
+    for messages in by_release.values() {
+        let before = strongest(messages.iter().map(|&i| config.posted_messages[i].score));
+
+        for &i in messages {
+            if let Some(reactions) = &reactions[i] {
+                config.posted_messages[i].score = score(reactions, &config.feedback_reactions);
+            }
+        }
+
+        let after = strongest(messages.iter().map(|&i| config.posted_messages[i].score));
+        let delta = after - before;
+
+        if delta == 0 {
+            continue;
+        }
+
+        let message = &config.posted_messages[messages[0]];
+
+        info!(
+            "Feedback on '{} - {}' changed by {}",
+            message.artist, message.album, delta
+        );
+
+        apply(&mut config.affinity, message, delta);
+    }
+}
+
+// The score furthest from zero
+fn strongest(scores: impl Iterator<Item = i32>) -> i32 {
+    scores.max_by_key(|s| s.abs()).unwrap_or_default()
+}
+
+fn score(reactions: &[(String, i32)], weights: &BTreeMap<String, i32>) -> i32 {
+    reactions
+        .iter()
+        .map(|(name, count)| weights.get(name).copied().unwrap_or_default() * count)
+        .sum()
+}
+
+fn apply(affinity: &mut BTreeMap<String, Affinity>, message: &PostedMessage, delta: i32) {
+    let entry = affinity.entry(message.artist.clone()).or_default();
+
+    entry.score += delta;
+    entry.genre = message.genre.clone();
+    entry.label = message.label.clone();
+}
+
+// Sums up the affinity of the release's artist and of every artist sharing
+// one of its genres or its label
+pub fn affinity(config: &Config, release: &Release) -> i32 {
+    let release_genres = release
+        .metallum
+        .as_ref()
+        .map(|m| genres(&m.genre))
+        .unwrap_or_default();
+
+    config
+        .affinity
+        .iter()
+        .map(|(artist, a)| {
+            let mut weight = 0;
+
+            if normalize::same_artist(artist, &release.artist) {
+                weight += ARTIST_WEIGHT;
+            }
+
+            weight += genres(&a.genre)
+                .iter()
+                .filter(|g| release_genres.contains(g))
+                .count() as i32;
+
+            if !a.label.is_empty() && normalize::text(&a.label) == normalize::text(&release.label) {
+                weight += 1;
+            }
+
+            a.score * weight
+        })
+        .sum()
+}
+
+// ie. "Melodic Death Metal/Metalcore" -> ["melodic death metal", "metalcore"]
+pub fn genres(genre: &str) -> Vec<String> {
+    genre
+        .split(['/', ',', ';'])
+        .map(|g| g.trim().to_lowercase())
+        .filter(|g| !g.is_empty())
+        .collect()
+}
+
+// The message a posted release is recorded as
+pub fn posted_message(
+    channel: &str,
+    ts: Option<String>,
+    post_at: Option<i64>,
+    release: &Release,
+) -> PostedMessage {
+    debug!(
+        "Recording message for '{} - {}' in channel '{}'",
+        release.artist, release.album, channel
+    );
+
+    PostedMessage {
+        channel: channel.to_string(),
+        ts,
+        post_at,
+        date: release.date,
+        artist: release.artist.clone(),
+        album: release.album.clone(),
+        genre: release
+            .metallum
+            .as_ref()
+            .map(|m| m.genre.clone())
+            .unwrap_or_default(),
+        label: release.label.clone(),
+        score: 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::MetallumArtistInfo;
+    use chrono::NaiveDate;
+
+    fn release(artist: &str, genre: &str, label: &str) -> Release {
+        let mut release: Release = serde_json::from_value(serde_json::json!({
+            "date": "2023-03-03",
+            "artist": artist,
+            "album": "Album",
+            "label": label,
+            "skip_reasons": [],
+        }))
+        .unwrap();
+
+        release.metallum = Some(MetallumArtistInfo {
+            genre: genre.to_string(),
+            ..Default::default()
+        });
+
+        release
+    }
+
+    fn affinity(score: i32, genre: &str, label: &str) -> Affinity {
+        Affinity {
+            score,
+            genre: genre.to_string(),
+            label: label.to_string(),
+        }
+    }
+
+    fn reactions(list: &[(&str, i32)]) -> Option<Vec<(String, i32)>> {
+        Some(list.iter().map(|(n, c)| (n.to_string(), *c)).collect())
+    }
+
+    #[test]
+    fn score_weights_reactions() {
+        let weights = BTreeMap::from([("fire".to_string(), 1), ("-1".to_string(), -2)]);
+
+        assert_eq!(score(&[], &weights), 0);
+        assert_eq!(
+            score(
+                &[
+                    ("fire".to_string(), 3),
+                    ("-1".to_string(), 1),
+                    ("eyes".to_string(), 5)
+                ],
+                &weights
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn affinity_by_artist_genre_and_label() {
+        let config = Config {
+            affinity: BTreeMap::from([
+                (
+                    "Enslaved".to_string(),
+                    affinity(2, "Progressive Black Metal", ""),
+                ),
+                (
+                    "Wolves in the Throne Room".to_string(),
+                    affinity(-1, "Atmospheric Black Metal", "Relapse"),
+                ),
+                ("Opeth".to_string(), affinity(5, "Progressive Metal", "")),
+            ]),
+            ..Default::default()
+        };
+
+        // The artist counts three times and their genre once
+        assert_eq!(
+            super::affinity(
+                &config,
+                &release("ENSLAVED", "Progressive Black Metal", "Nuclear Blast")
+            ),
+            2 * 4
+        );
+        // Only a shared label, and one of two genres
+        assert_eq!(
+            super::affinity(
+                &config,
+                &release("Mare", "Progressive Black Metal/Doom Metal", "relapse")
+            ),
+            2 - 1
+        );
+        assert_eq!(
+            super::affinity(&config, &release("Gojira", "Groove Metal", "Roadrunner")),
+            0
+        );
+        assert_eq!(
+            genres("Melodic Death Metal/Metalcore, Folk;"),
+            ["melodic death metal", "metalcore", "folk"]
+        );
+    }
+
+    #[test]
+    fn release_posted_twice_counts_once() {
+        let posted = |channel: &str, album: &str| {
+            let mut release = release("Enslaved", "Progressive Black Metal", "Nuclear Blast");
+            release.album = album.to_string();
+            release.date = NaiveDate::from_ymd_opt(2023, 3, 3).unwrap();
+
+            posted_message(channel, Some("1.0".to_string()), None, &release)
+        };
+
+        let mut config = Config {
+            posted_messages: vec![
+                posted("C1", "Heimdal"),
+                posted("C2", "Heimdal"),
+                posted("D1", "Heimdal (Deluxe Edition)"),
+                posted("C1", "Utgard"),
+            ],
+            ..Default::default()
+        };
+
+        learn(
+            &mut config,
+            &[
+                reactions(&[("fire", 2)]),
+                reactions(&[("fire", 1)]),
+                reactions(&[("fire", 3)]),
+                None,
+            ],
+        );
+
+        // Heimdal counts with its strongest message, Utgard hasn't been read
+        assert_eq!(config.affinity["Enslaved"].score, 3);
+        assert_eq!(
+            config
+                .posted_messages
+                .iter()
+                .map(|m| m.score)
+                .collect::<Vec<i32>>(),
+            [2, 1, 3, 0]
+        );
+
+        // Reading the same reactions again changes nothing
+        learn(
+            &mut config,
+            &[
+                reactions(&[("fire", 2)]),
+                reactions(&[("fire", 1)]),
+                reactions(&[("fire", 3)]),
+                reactions(&[("-1", 1)]),
+            ],
+        );
+
+        assert_eq!(config.affinity["Enslaved"].score, 2);
+    }
+}
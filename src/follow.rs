@@ -0,0 +1,170 @@
+use crate::config::{Follow, Release, ReleaseStatus};
+use crate::normalize;
+use crate::notifier::Notice;
+use chrono::Local;
+use log::info;
+
+// ie. "https://www.metal-archives.com/bands/Enslaved/159" -> "159"
+pub fn metallum_id(url: &str) -> Option<&str> {
+    url.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+}
+
+// Spotify and Metal Archives IDs only match once the release has been enriched
+pub fn is_followed(follows: &[Follow], release: &Release) -> bool {
+    follows.iter().any(|follow| {
+        let name = follow
+            .name
+            .as_ref()
+            .is_some_and(|name| normalize::same_artist(name, &release.artist));
+
+        let spotify = match (&follow.spotify_id, &release.spotify) {
+            (Some(id), Some(spotify)) => *id == spotify.id,
+            _ => false,
+        };
+
+        let metallum = match (&follow.metallum_id, &release.metallum) {
+            (Some(id), Some(metallum)) => metallum_id(&metallum.url) == Some(id.as_str()),
+            _ => false,
+        };
+
+        name || spotify || metallum
+    })
+}
+
+// Flags releases by followed artists, and unflags them when an artist is
+// unfollowed. Followed releases bypass the skip filters, so skips from earlier
+// runs are undone.
+pub fn mark(follows: &[Follow], releases: &mut [Release]) {
+    for release in releases {
+        release.followed = is_followed(follows, release);
+
+        if release.followed && release.skip {
+            release.skip = false;
+            release.skip_reasons.clear();
+        }
+    }
+}
+
+// Notices for upcoming releases by followed artists that haven't been announced
+// yet; they're flagged as announced
+pub fn announce(releases: &mut [Release]) -> Vec<Notice> {
+    let today = Local::now().date_naive();
+
+    releases
+        .iter_mut()
+        .filter(|r| r.followed && !r.announced && r.date >= today)
+        .filter(|r| r.status != Some(ReleaseStatus::Cancelled))
+        .map(|r| {
+            info!(
+                "Announcing '{} - {}' by a followed artist",
+                r.artist, r.album
+            );
+
+            r.announced = true;
+
+            Notice {
+                text: format!(
+                    "New release by {}: {}, out {}",
+                    r.artist,
+                    r.album,
+                    r.date.format("%b %-d")
+                ),
+                release: r.clone(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{MetallumArtistInfo, SpotifyArtistInfo};
+
+    fn release(artist: &str, date: chrono::NaiveDate) -> Release {
+        Release {
+            date,
+            artist: artist.to_string(),
+            album: "Heimdal".to_string(),
+            label: "Nuclear Blast".to_string(),
+            skip: true,
+            skip_reasons: vec!["follower count too low".to_string()],
+            spotify: None,
+            metallum: None,
+            updated: None,
+            triage: None,
+            status: None,
+            date_history: vec![],
+            followed: false,
+            announced: false,
+            looked_up: false,
+            notified: false,
+        }
+    }
+
+    #[test]
+    fn metallum_ids() {
+        assert_eq!(
+            metallum_id("https://www.metal-archives.com/bands/Enslaved/159"),
+            Some("159")
+        );
+        assert_eq!(
+            metallum_id("https://www.metal-archives.com/bands/Enslaved/159/"),
+            Some("159")
+        );
+        assert_eq!(
+            metallum_id("https://www.metal-archives.com/bands/Enslaved"),
+            None
+        );
+    }
+
+    #[test]
+    fn mark_and_announce() {
+        let today = Local::now().date_naive();
+        let follows = vec![
+            Follow {
+                name: Some("The Ocean Collective".to_string()),
+                ..Default::default()
+            },
+            Follow {
+                spotify_id: Some("1AdOhHRnFG0mvTVS1nVX6M".to_string()),
+                ..Default::default()
+            },
+            Follow {
+                metallum_id: Some("159".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let mut releases = vec![
+            release("The Ocean", today),
+            release("Enslaved", today),
+            release("Kvelertak", today),
+            release("Mastodon", today),
+            release("Ocean Collective Band", today),
+        ];
+        releases[1].metallum = Some(MetallumArtistInfo {
+            url: "https://www.metal-archives.com/bands/Enslaved/159".to_string(),
+            ..Default::default()
+        });
+        releases[2].spotify = Some(SpotifyArtistInfo {
+            id: "1AdOhHRnFG0mvTVS1nVX6M".to_string(),
+            ..Default::default()
+        });
+        releases[3].status = Some(ReleaseStatus::Cancelled);
+        releases[3].artist = "The Ocean".to_string();
+
+        mark(&follows, &mut releases);
+
+        let followed: Vec<bool> = releases.iter().map(|r| r.followed).collect();
+        assert_eq!(followed, vec![true, true, true, true, false]);
+        assert!(!releases[0].skip && releases[0].skip_reasons.is_empty());
+        assert!(releases[4].skip);
+
+        // Cancelled releases aren't announced, and nothing is announced twice
+        assert_eq!(announce(&mut releases).len(), 3);
+        assert!(announce(&mut releases).is_empty());
+    }
+}
@@ -0,0 +1,476 @@
+use crate::config::{self, Affinity, Config, Follow, ImportSource, MetallumArtistInfo};
+use crate::{follow, normalize, release, AppError};
+use log::info;
+use rspotify::{prelude::*, scopes, AuthCodeSpotify, Credentials, OAuth};
+use serde_json::Value;
+use std::collections::HashMap;
+
+// Affinity given to artists followed on Spotify
+const SPOTIFY_SCORE: i32 = 2;
+
+// Affinity given to the most played Last.fm artists; it goes down to 1 for the
+// least played of the imported ones
+const LASTFM_MAX_SCORE: i32 = 3;
+
+// An artist from listening history
+#[derive(Debug, PartialEq)]
+struct Artist {
+    name: String,
+    spotify_id: Option<String>,
+    score: i32,
+}
+
+// The store is only loaded for the update at the end; the OAuth prompt and
+// Metal Archives lookups take a while, and jobs may save it meanwhile
+pub async fn run(
+    config: &Config,
+    source: &ImportSource,
+    keep_unmatched: bool,
+) -> Result<(), AppError> {
+    let artists = match source {
+        ImportSource::Spotify { redirect_uri } => spotify(config, redirect_uri).await?,
+        ImportSource::Lastfm { file, limit } => {
+            let contents = std::fs::read_to_string(file).map_err(|e| {
+                AppError::GenericError(format!("Could not read '{}': {}", file.display(), e))
+            })?;
+
+            lastfm(&contents, *limit)?
+        }
+    };
+
+    info!("Looking up {} artists on Metal Archives", artists.len());
+
+    // A failed lookup would look like the band isn't there, so it stops the
+    // import; nothing is saved and it can be run again
+    let names: Vec<String> = artists.iter().map(|a| a.name.clone()).collect();
+    let found = release::find_metallum_artists(&names).await?;
+
+    let (mut followed, mut unmatched) = (0, 0);
+
+    config::update(|config| {
+        for (artist, metallum) in artists.iter().zip(&found) {
+            if metallum.is_none() {
+                unmatched += 1;
+
+                if !keep_unmatched {
+                    info!("Skipping '{}' - not on Metal Archives", artist.name);
+                    continue;
+                }
+            }
+
+            let follow = Follow {
+                name: Some(artist.name.clone()),
+                spotify_id: artist.spotify_id.clone(),
+                metallum_id: metallum
+                    .as_ref()
+                    .and_then(|m| follow::metallum_id(&m.url))
+                    .map(String::from),
+            };
+
+            if add_follow(&mut config.follows, follow) {
+                followed += 1;
+            }
+
+            seed_affinity(config, artist, metallum.as_ref());
+        }
+
+        Ok(())
+    })?;
+
+    println!(
+        "Followed {} new artists ({} not on Metal Archives)",
+        followed, unmatched
+    );
+
+    Ok(())
+}
+
+// Artists the user follows; uses the authorization code flow, so the user has
+// to allow access in a browser and paste back the URL they're sent to
+async fn spotify(config: &Config, redirect_uri: &str) -> Result<Vec<Artist>, AppError> {
+    let creds = Credentials::new(&config.spotify_client_id, &config.spotify_client_secret);
+    let oauth = OAuth {
+        redirect_uri: redirect_uri.to_string(),
+        scopes: scopes!("user-follow-read"),
+        ..Default::default()
+    };
+
+    let client = AuthCodeSpotify::new(creds, oauth);
+
+    println!(
+        "Open this URL and allow access:\n\n{}\n",
+        client.get_authorize_url(false)?
+    );
+
+    let url = config::ask_question("URL you were redirected to: ", true)?;
+    let code = client
+        .parse_response_code(&url)
+        .ok_or(AppError::GenericError(
+            "No authorization code in the URL".to_string(),
+        ))?;
+
+    client.request_token(&code).await?;
+
+    let mut artists = vec![];
+    let mut after = None;
+
+    // Followed artists are paged by cursor, 50 at most per page
+    loop {
+        let page = client
+            .current_user_followed_artists(after.as_deref(), Some(50))
+            .await?;
+
+        artists.extend(page.items.into_iter().map(|a| Artist {
+            name: a.name,
+            spotify_id: Some(a.id.to_string()),
+            score: SPOTIFY_SCORE,
+        }));
+
+        after = page.cursors.and_then(|c| c.after);
+
+        if page.next.is_none() || after.is_none() {
+            break;
+        }
+    }
+
+    info!("Found {} followed artists on Spotify", artists.len());
+
+    Ok(artists)
+}
+
+// The most played artists, most played first; scores go down with rank
+fn lastfm(contents: &str, limit: usize) -> Result<Vec<Artist>, AppError> {
+    let trimmed = contents.trim_start();
+
+    let plays = if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        let mut plays = Plays::default();
+        plays.walk(&serde_json::from_str(trimmed)?);
+        plays
+    } else if trimmed.starts_with("#AUDIOSCROBBLER") {
+        scrobbler_log(trimmed)
+    } else {
+        csv(trimmed)
+    };
+
+    let top = plays.top(limit);
+    let count = top.len().max(1) as i32;
+
+    Ok(top
+        .into_iter()
+        .enumerate()
+        .map(|(rank, name)| Artist {
+            name,
+            spotify_id: None,
+            score: LASTFM_MAX_SCORE - rank as i32 * LASTFM_MAX_SCORE / count,
+        })
+        .collect())
+}
+
+// Play counts per artist; spellings of the same artist are counted together
+// under the first one seen
+#[derive(Default)]
+struct Plays {
+    counts: HashMap<String, (String, usize)>,
+    order: Vec<String>,
+}
+
+impl Plays {
+    fn add(&mut self, name: &str, count: usize) {
+        let name = name.trim();
+        let key = normalize::artist(name);
+
+        if key.is_empty() {
+            return;
+        }
+
+        let entry = self.counts.entry(key.clone()).or_insert_with(|| {
+            self.order.push(key);
+            (name.to_string(), 0)
+        });
+
+        entry.1 += count;
+    }
+
+    // Scrobbles from the recent tracks API or export tools have an "artist"
+    // that's a name or an object with one; top artist lists have an array of
+    // artists with play counts
+    fn walk(&mut self, value: &Value) {
+        match value {
+            Value::Array(items) => items.iter().for_each(|v| self.walk(v)),
+            Value::Object(map) => match map.get("artist") {
+                Some(Value::Array(artists)) => {
+                    for artist in artists {
+                        if let Some(name) = artist.get("name").and_then(Value::as_str) {
+                            self.add(name, playcount(artist));
+                        }
+                    }
+                }
+                Some(Value::String(name)) => self.add(name, 1),
+                Some(artist) => {
+                    if let Some(name) = artist
+                        .get("#text")
+                        .or_else(|| artist.get("name"))
+                        .and_then(Value::as_str)
+                    {
+                        self.add(name, 1);
+                    }
+                }
+                None => map.values().for_each(|v| self.walk(v)),
+            },
+            _ => {}
+        }
+    }
+
+    fn top(self, limit: usize) -> Vec<String> {
+        let mut artists: Vec<(String, usize)> = self
+            .order
+            .iter()
+            .filter_map(|key| self.counts.get(key).cloned())
+            .collect();
+
+        // Stable, so ties keep the order they were first seen in
+        artists.sort_by_key(|a| std::cmp::Reverse(a.1));
+        artists.truncate(limit);
+
+        artists.into_iter().map(|(name, _)| name).collect()
+    }
+}
+
+// Last.fm sends play counts as strings
+fn playcount(artist: &Value) -> usize {
+    match artist.get("playcount") {
+        Some(Value::String(s)) => s.parse().unwrap_or(1),
+        Some(Value::Number(n)) => n.as_u64().unwrap_or(1) as usize,
+        _ => 1,
+    }
+}
+
+// Portable players' log: tab separated, artist first, and "L" (listened) or
+// "S" (skipped) in the sixth column
+fn scrobbler_log(contents: &str) -> Plays {
+    let mut plays = Plays::default();
+
+    for line in contents.lines().filter(|l| !l.starts_with('#')) {
+        let fields: Vec<&str> = line.split('\t').collect();
+
+        if fields.get(5) == Some(&"S") {
+            continue;
+        }
+
+        plays.add(fields[0], 1);
+    }
+
+    plays
+}
+
+// One scrobble per row; the artist is the "artist" column if there's a header,
+// otherwise the first one (as in "artist,album,track,date" exports)
+fn csv(contents: &str) -> Plays {
+    let mut plays = Plays::default();
+    let mut lines = contents.lines().peekable();
+
+    let column = match lines.peek().map(|l| csv_fields(l)) {
+        Some(header) => match header.iter().position(|f| f.eq_ignore_ascii_case("artist")) {
+            Some(column) => {
+                lines.next();
+                column
+            }
+            None => 0,
+        },
+        None => 0,
+    };
+
+    for line in lines {
+        if let Some(artist) = csv_fields(line).get(column) {
+            plays.add(artist, 1);
+        }
+    }
+
+    plays
+}
+
+// Splits a CSV line, minding quoted fields and doubled quotes
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+
+    fields.push(field);
+    fields
+}
+
+// Adds a follow, or fills in the IDs of an existing follow of the same artist;
+// returns whether it was new
+fn add_follow(follows: &mut Vec<Follow>, follow: Follow) -> bool {
+    let same = |a: &Option<String>, b: &Option<String>| a.is_some() && a == b;
+
+    let existing = follows.iter_mut().find(|f| {
+        let name = match (&f.name, &follow.name) {
+            (Some(a), Some(b)) => normalize::same_artist(a, b),
+            _ => false,
+        };
+
+        name || same(&f.spotify_id, &follow.spotify_id) || same(&f.metallum_id, &follow.metallum_id)
+    });
+
+    match existing {
+        Some(existing) => {
+            existing.name = existing.name.take().or(follow.name);
+            existing.spotify_id = existing.spotify_id.take().or(follow.spotify_id);
+            existing.metallum_id = existing.metallum_id.take().or(follow.metallum_id);
+            false
+        }
+        None => {
+            follows.push(follow);
+            true
+        }
+    }
+}
+
+// Only artists without an affinity yet are seeded, so importing again doesn't
+// undo feedback
+fn seed_affinity(config: &mut Config, artist: &Artist, metallum: Option<&MetallumArtistInfo>) {
+    if config
+        .affinity
+        .keys()
+        .any(|name| normalize::same_artist(name, &artist.name))
+    {
+        return;
+    }
+
+    let (genre, label) = match metallum {
+        Some(m) if m.last_label != "N/A" => (m.genre.clone(), m.last_label.clone()),
+        Some(m) => (m.genre.clone(), String::new()),
+        None => (String::new(), String::new()),
+    };
+
+    config.affinity.insert(
+        artist.name.clone(),
+        Affinity {
+            score: artist.score,
+            genre,
+            label,
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(artists: &[Artist]) -> Vec<(&str, i32)> {
+        artists.iter().map(|a| (a.name.as_str(), a.score)).collect()
+    }
+
+    #[test]
+    fn lastfm_recent_tracks() {
+        let json = r##"[{"recenttracks": {"track": [
+            {"artist": {"#text": "Enslaved"}, "name": "Kingdom"},
+            {"artist": {"#text": "Mastodon"}, "name": "Oblivion"},
+            {"artist": {"#text": "enslaved"}, "name": "Heimdal"},
+            {"artist": {"name": "Mötley Crüe"}, "name": "Kickstart My Heart"}
+        ]}}]"##;
+
+        let artists = lastfm(json, 2).unwrap();
+
+        assert_eq!(names(&artists), vec![("Enslaved", 3), ("Mastodon", 2)]);
+    }
+
+    #[test]
+    fn lastfm_top_artists() {
+        let json = r#"{"topartists": {"artist": [
+            {"name": "Gojira", "playcount": "12"},
+            {"name": "Opeth", "playcount": "30"},
+            {"name": "Gojira", "playcount": 20}
+        ]}}"#;
+
+        let artists = lastfm(json, 10).unwrap();
+
+        assert_eq!(names(&artists), vec![("Gojira", 3), ("Opeth", 2)]);
+    }
+
+    #[test]
+    fn lastfm_csv_and_scrobbler_log() {
+        let csv = "Opeth,Blackwater Park,The Drapery Falls,01 Jan 2023 10:00\n\
+                   \"Coheed and Cambria\",\"Vaxis, Act II\",Shoulders,01 Jan 2023 10:10\n\
+                   Coheed & Cambria,Vaxis II,Window of the Waking Mind,01 Jan 2023 10:20\n";
+
+        assert_eq!(
+            names(&lastfm(csv, 10).unwrap()),
+            vec![("Coheed and Cambria", 3), ("Opeth", 2)]
+        );
+
+        let header = "uts,utc_time,artist,album,track\n1,x,Baroness,Stone,Last Word\n";
+
+        assert_eq!(names(&lastfm(header, 10).unwrap()), vec![("Baroness", 3)]);
+
+        let log = "#AUDIOSCROBBLER/1.1\n#TZ/UNKNOWN\n\
+                   Baroness\tStone\tLast Word\t1\t200\tL\t1690000000\n\
+                   Kvelertak\tEndling\tKrøterveg te helvete\t1\t300\tS\t1690000300\n";
+
+        assert_eq!(names(&lastfm(log, 10).unwrap()), vec![("Baroness", 3)]);
+    }
+
+    #[test]
+    fn csv_quotes() {
+        assert_eq!(
+            csv_fields(r#"a,"b, c","say ""hi""",d"#),
+            vec!["a", "b, c", "say \"hi\"", "d"]
+        );
+    }
+
+    #[test]
+    fn add_follow_merges() {
+        let mut follows = vec![Follow {
+            name: Some("The Ocean".to_string()),
+            ..Default::default()
+        }];
+
+        assert!(!add_follow(
+            &mut follows,
+            Follow {
+                name: Some("The Ocean Collective".to_string()),
+                spotify_id: Some("abc".to_string()),
+                metallum_id: Some("1234".to_string()),
+            }
+        ));
+        assert!(add_follow(
+            &mut follows,
+            Follow {
+                name: Some("Enslaved".to_string()),
+                ..Default::default()
+            }
+        ));
+        assert!(!add_follow(
+            &mut follows,
+            Follow {
+                name: Some("Renamed".to_string()),
+                spotify_id: Some("abc".to_string()),
+                ..Default::default()
+            }
+        ));
+
+        assert_eq!(follows.len(), 2);
+        assert_eq!(
+            follows[0],
+            Follow {
+                name: Some("The Ocean".to_string()),
+                spotify_id: Some("abc".to_string()),
+                metallum_id: Some("1234".to_string()),
+            }
+        );
+    }
+}
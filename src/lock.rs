@@ -0,0 +1,272 @@
+use crate::AppError;
+use log::{debug, warn};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::time::{Duration, Instant};
+use tokio::runtime::RuntimeFlavor;
+
+// How long a store write waits for another one to finish
+const STORE_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A lock file that's still empty is being written by its owner, unless it's
+// been empty for this long
+const EMPTY_LOCK_AGE: Duration = Duration::from_secs(5);
+
+// Held while metalpal runs its jobs (a cron run or the daemon) so two of them
+// don't fetch and post at the same time; removed on drop
+pub struct RunLock {
+    path: String,
+}
+
+impl RunLock {
+    // Locks the jobs working on the store at `store_path`
+    pub fn acquire(store_path: &str) -> Result<Self, AppError> {
+        Self::acquire_at(&format!("{}.lock", store_path))
+    }
+
+    fn acquire_at(path: &str) -> Result<Self, AppError> {
+        match try_create(path)? {
+            true => Ok(Self {
+                path: path.to_string(),
+            }),
+            false => Err(AppError::GenericError(format!(
+                "Another metalpal (pid {}) is running; remove '{}' if it isn't",
+                fs::read_to_string(path).unwrap_or_default().trim(),
+                path
+            ))),
+        }
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        remove(&self.path);
+    }
+}
+
+// Held for a single load, change and save of the release store, so commands
+// running alongside a job (ie. the TUI or the API) don't overwrite each other's
+// writes; removed on drop
+pub struct StoreLock {
+    path: String,
+}
+
+impl StoreLock {
+    // Waits for whoever is saving the store at `store_path` to finish
+    pub fn acquire(store_path: &str) -> Result<Self, AppError> {
+        Self::acquire_at(&format!("{}.write.lock", store_path), STORE_LOCK_TIMEOUT)
+    }
+
+    fn acquire_at(path: &str, timeout: Duration) -> Result<Self, AppError> {
+        let start = Instant::now();
+
+        if try_create(path)? {
+            return Ok(Self {
+                path: path.to_string(),
+            });
+        }
+
+        // Saves are called from async code (daemon jobs, API handlers), so the
+        // wait mustn't hold up the other tasks on this thread
+        blocking(|| loop {
+            if start.elapsed() > timeout {
+                return Err(AppError::GenericError(format!(
+                    "Timed out waiting for '{}'; remove it if no metalpal is running",
+                    path
+                )));
+            }
+
+            std::thread::sleep(Duration::from_millis(50));
+
+            if try_create(path)? {
+                return Ok(Self {
+                    path: path.to_string(),
+                });
+            }
+        })
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        remove(&self.path);
+    }
+}
+
+// Runs `f` off the tokio worker thread it's called on, if any. A current thread
+// runtime has no other thread to move its tasks to, so there it just runs.
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+// Creates the lock file with our pid in it; false if a running process holds
+// it. A lock left behind by a process that died (ie. on a fatal error) is
+// removed first.
+fn try_create(path: &str) -> Result<bool, AppError> {
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                write!(file, "{}", std::process::id())?;
+                debug!("Acquired lock '{}'", path);
+
+                return Ok(true);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let pid = fs::read_to_string(path).unwrap_or_default();
+
+                if is_held(path, pid.trim()) {
+                    return Ok(false);
+                }
+
+                warn!("Removing stale lock '{}' (pid {})", path, pid.trim());
+
+                match fs::remove_file(path) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(false)
+}
+
+fn is_held(path: &str, pid: &str) -> bool {
+    if !pid.is_empty() {
+        return is_running(pid);
+    }
+
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(|modified| modified.elapsed().unwrap_or_default() < EMPTY_LOCK_AGE)
+        .unwrap_or(false)
+}
+
+fn remove(path: &str) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("Could not remove lock '{}': {}", path, e);
+    }
+}
+
+// Signal 0 only checks whether the process exists; EPERM means it does, but
+// belongs to another user
+#[cfg(unix)]
+fn is_running(pid: &str) -> bool {
+    let pid: libc::pid_t = match pid.parse() {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: &str) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lock_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "metalpal-test-{}-{}.lock",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        path.display().to_string()
+    }
+
+    #[test]
+    fn running_processes() {
+        assert!(is_running(&std::process::id().to_string()));
+        // Beyond any pid_max
+        assert!(!is_running("2147483646"));
+        assert!(!is_running("abc"));
+        assert!(!is_running("-1"));
+    }
+
+    #[test]
+    fn run_lock_is_exclusive() {
+        let path = lock_path("run");
+
+        let lock = RunLock::acquire_at(&path).unwrap();
+        assert!(RunLock::acquire_at(&path).is_err());
+
+        drop(lock);
+        assert!(!std::path::Path::new(&path).exists());
+        assert!(RunLock::acquire_at(&path).is_ok());
+    }
+
+    #[test]
+    fn stale_locks_are_removed() {
+        let path = lock_path("stale");
+
+        fs::write(&path, "2147483646").unwrap();
+        let lock = RunLock::acquire_at(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+        drop(lock);
+
+        // Freshly created by someone who hasn't written their pid yet
+        fs::write(&path, "").unwrap();
+        assert!(RunLock::acquire_at(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn store_lock_waits() {
+        let path = lock_path("store");
+
+        let lock = StoreLock::acquire_at(&path, Duration::from_millis(100)).unwrap();
+        assert!(StoreLock::acquire_at(&path, Duration::from_millis(100)).is_err());
+
+        let waiting = {
+            let path = path.clone();
+            std::thread::spawn(move || StoreLock::acquire_at(&path, Duration::from_secs(5)).is_ok())
+        };
+
+        std::thread::sleep(Duration::from_millis(200));
+        drop(lock);
+
+        assert!(waiting.join().unwrap());
+    }
+
+    // With a single worker, a wait that blocked the thread would keep the
+    // task holding the lock from ever releasing it
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn store_lock_waits_off_the_runtime() {
+        let path = lock_path("async");
+
+        let lock = StoreLock::acquire_at(&path, Duration::from_millis(100)).unwrap();
+
+        let holder = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            drop(lock);
+        });
+        let waiting = {
+            let path = path.clone();
+            tokio::spawn(
+                async move { StoreLock::acquire_at(&path, Duration::from_secs(2)).is_ok() },
+            )
+        };
+
+        assert!(waiting.await.unwrap());
+        holder.await.unwrap();
+    }
+}
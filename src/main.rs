@@ -1,14 +1,33 @@
+mod api;
+mod bot;
+mod calendar;
 mod config;
+mod daemon;
 mod display;
 mod error;
+mod export;
+mod feed;
+mod feedback;
+mod follow;
+mod import;
+mod lock;
+mod merge;
+mod metrics;
+mod normalize;
+mod notifier;
+mod pipeline;
 mod release;
+mod server;
+mod site;
 mod slack;
+mod subscription;
+mod tui;
 
 // Q: What's the diff between 'extern' and 'use'
 extern crate prettytable;
 extern crate term;
 
-use crate::config::Config;
+use crate::config::{Command, Config, OutputFormat, ScheduledAction};
 use crate::error::AppError;
 use clap::Parser;
 use log::{debug, error, info};
@@ -23,23 +42,54 @@ async fn main() {
         Err(e) => fatal_error(e.to_string()),
     };
 
+    if let Some(command) = &cli.command {
+        if let Err(e) = run_command(&cli, &config, command).await {
+            fatal_error(e.to_string());
+        }
+
+        return;
+    }
+
+    if let Some(report) = &cli.report {
+        metrics::report_to(report.clone());
+    }
+
+    // Cron may start a run while the daemon (or a previous run) is still busy
+    let _lock = match lock::RunLock::acquire(&config.full_path) {
+        Ok(lock) => lock,
+        Err(e) => fatal_error(e.to_string()),
+    };
+
+    // Check every target up front so a bad channel or token is reported before any work is done
+    let targets = match pipeline::Targets::setup(&config, cli.disable_slack).await {
+        Ok(targets) => targets,
+        Err(e) => fatal_error(e.to_string()),
+    };
+
     // Outdated releases?
     if release::out_of_date(&config) {
-        match release::fetch_releases().await {
-            Ok(releases) => {
-                debug!("Fetched {} releases", releases.len()); // Q: Intellij marks releases.len() as an error: `usize` doesn't implement `Display` (required by {}); any way to fix?
-                config.last_update = chrono::Utc::now();
-                config.releases = releases
+        match pipeline::fetch(&mut config).await {
+            Ok(notices) => {
+                if let Err(e) = targets.announce(&notices).await {
+                    fatal_error(e.to_string());
+                }
+            }
+            Err(e @ AppError::ScraperError(_)) => {
+                targets.alert_scraper(&config, &e).await;
+                scraper_broken(e.to_string());
             }
             Err(e) => fatal_error(e.to_string()),
-        };
+        }
     } else {
         debug!("Config is up to date; skipping fetch...");
     }
 
-    // Save
-    if let Err(e) = config::save_config(&config) {
-        // Q: My IDE can't tell that to_string exists - why not?
+    if let Err(e) = targets.collect_feedback(&mut config).await {
+        fatal_error(e.to_string());
+    }
+
+    // Weekly export doesn't depend on there being releases today
+    if let Err(e) = targets.weekly_export(&mut config).await {
         fatal_error(e.to_string());
     }
 
@@ -47,56 +97,127 @@ async fn main() {
     let mut releases_today = release::get_releases_today(&config.releases);
 
     if releases_today.is_empty() {
+        // Other formats are usually piped, so they still get an empty document
+        if cli.output != OutputFormat::Table {
+            if let Err(e) = display::output(cli.output, &[], &[], cli.include_skipped) {
+                fatal_error(e.to_string());
+            }
+        }
+
         exit("No releases today!".to_string());
     }
 
-    // Enrich today's releases with release.spotify metadata
-    if let Err(e) = release::enrich_with_spotify(
-        config.spotify_client_id.clone(),
-        config.spotify_client_secret.clone(),
-        &mut releases_today,
-    )
-    .await
-    {
-        fatal_error(e.to_string())
-    };
-
-    // Do spotify-based filtering
-    release::set_skip_spotify(&config, &mut releases_today);
-
-    // // Enrich matching releases with metallum metadata
-    if let Err(e) = release::enrich_with_metallum(&mut releases_today).await {
-        fatal_error(e.to_string())
-    };
+    if let Err(e) = pipeline::enrich(&mut config, &mut releases_today).await {
+        fatal_error(e.to_string());
+    }
 
-    release::set_skip_metallum(&config, &mut releases_today);
+    let valid_releases = release::filter_valid_releases(&config, &releases_today);
 
-    // Merge today's releases with existing releases
-    release::merge_releases(&mut config.releases, &releases_today);
+    if let Err(e) = display::output(
+        cli.output,
+        &valid_releases,
+        &releases_today,
+        cli.include_skipped,
+    ) {
+        fatal_error(e.to_string());
+    }
 
-    // Save again
-    if let Err(e) = config::save_config(&config) {
-        // Q: My IDE can't tell that to_string exists - why not?
+    if let Err(e) = targets.notify(&mut config, &valid_releases).await {
         fatal_error(e.to_string());
     }
 
-    let valid_releases = release::filter_valid_releases(&releases_today);
+    metrics::write_report("ok", None);
+}
+
+async fn run_command(
+    cli: &config::Cli,
+    config: &Config,
+    command: &Command,
+) -> Result<(), AppError> {
+    match command {
+        Command::Scheduled { action } => {
+            if config.slack_bot_token.is_empty() {
+                return Err(AppError::SlackError(
+                    "Slack bot token is not configured".to_string(),
+                ));
+            }
 
-    display::display(&valid_releases, &releases_today);
+            let slack_client = slack::Slack::new(config);
 
-    // Maybe send Slack notices
-    if !cli.disable_slack && !config.slack_channels.is_empty() && !config.slack_bot_token.is_empty()
-    {
-        let slack_client = slack::Slack::new(&config);
+            match action {
+                ScheduledAction::List => {
+                    display::display_scheduled(&slack_client.list_scheduled().await?);
+                }
+                ScheduledAction::Cancel { channel, id, all } => {
+                    if *all {
+                        for m in slack_client.list_scheduled().await? {
+                            let channel = m.channel_id.unwrap_or_default();
+                            let id = m.id.unwrap_or_default();
 
-        if let Err(e) = slack_client.post_releases(&valid_releases).await {
-            fatal_error(e.to_string());
+                            slack_client.cancel_scheduled(&channel, &id).await?;
+                            info!("Cancelled scheduled message '{}' in '{}'", id, channel);
+                        }
+                    } else if let (Some(channel), Some(id)) = (channel, id) {
+                        slack_client.cancel_scheduled(channel, id).await?;
+                        info!("Cancelled scheduled message '{}' in '{}'", id, channel);
+                    }
+                }
+            }
+        }
+        Command::Bot { listen } => bot::run(config, *listen).await?,
+        Command::Calendar { file, listen } => {
+            write_or_serve(config, calendar::DOCUMENT, file, listen).await?
+        }
+        Command::Site { dir } => {
+            let dir = match (dir, &config.site_dir) {
+                (Some(dir), _) => dir.clone(),
+                (None, Some(dir)) => dir.into(),
+                (None, None) => {
+                    return Err(AppError::GenericError(
+                        "No site directory given and site_dir is not configured".to_string(),
+                    ))
+                }
+            };
+
+            site::generate(config, &dir)?;
+        }
+        Command::Api { listen } => api::serve(*listen).await?,
+        Command::Tui => tui::run()?,
+        Command::Daemon { metrics_listen } => {
+            daemon::run(config, cli.disable_slack, *metrics_listen).await?
+        }
+        Command::Feed { file, listen } => {
+            write_or_serve(config, feed::DOCUMENT, file, listen).await?
+        }
+        Command::Import {
+            source,
+            keep_unmatched,
+        } => import::run(config, source, *keep_unmatched).await?,
+    }
+
+    Ok(())
+}
+
+async fn write_or_serve(
+    config: &Config,
+    document: server::Document,
+    file: &Option<std::path::PathBuf>,
+    listen: &Option<std::net::SocketAddr>,
+) -> Result<(), AppError> {
+    match (file, listen) {
+        (_, Some(addr)) => server::serve(*addr, document).await?,
+        (Some(file), None) => {
+            std::fs::write(file, (document.render)(config))?;
+            info!("Wrote '{}'", file.display());
         }
+        (None, None) => print!("{}", (document.render)(config)),
     }
+
+    Ok(())
 }
 
-fn setup() -> config::CLI {
-    let cli = config::CLI::parse();
+fn setup() -> config::Cli {
+    let cli = config::Cli::parse();
 
     if cli.debug {
         env::set_var("RUST_LOG", "metalpal=debug");
@@ -111,7 +232,7 @@ fn setup() -> config::CLI {
 
 // Q: Should I return a String for errors or my own custom error?
 // My guess: implement Display trait on my custom type so it can be println!'d. Is this correct?
-fn load_or_setup_config(cli: &config::CLI) -> Result<Config, AppError> {
+fn load_or_setup_config(cli: &config::Cli) -> Result<Config, AppError> {
     match config::load_config() {
         Ok(config) => {
             debug!("Successfully loaded existing config");
@@ -126,10 +247,19 @@ fn load_or_setup_config(cli: &config::CLI) -> Result<Config, AppError> {
 
 fn exit(m: String) -> ! {
     info!("{}", m);
+    metrics::write_report("no_releases", None);
     std::process::exit(0);
 }
 
 fn fatal_error(m: String) -> ! {
     error!("{}", m);
+    metrics::write_report("error", Some(&m));
     std::process::exit(1);
 }
+
+// Distinct from other errors so cron wrappers and monitoring can tell them apart
+fn scraper_broken(m: String) -> ! {
+    error!("{}", m);
+    metrics::write_report("scraper_broken", Some(&m));
+    std::process::exit(2);
+}
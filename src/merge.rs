@@ -0,0 +1,173 @@
+use crate::release;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+// Three-way merge of the release store, for saving a store that was loaded a
+// while ago: `base` is the store as it was loaded, `ours` the store about to be
+// saved and `theirs` what's on disk now. What only one side changed is taken
+// from that side; when both changed the same value, ours wins. Objects are
+// merged key by key and releases by release ID; other lists are taken whole.
+pub fn merge(base: &Value, ours: &Value, theirs: &Value) -> Value {
+    merge_value("", Some(base), Some(ours), Some(theirs)).unwrap_or(Value::Null)
+}
+
+fn merge_value(
+    key: &str,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Value> {
+    if ours == base {
+        return theirs.cloned();
+    }
+
+    if theirs == base || theirs == ours {
+        return ours.cloned();
+    }
+
+    match (base, ours, theirs) {
+        (Some(Value::Object(b)), Some(Value::Object(o)), Some(Value::Object(t))) => {
+            Some(Value::Object(merge_objects(b, o, t)))
+        }
+        (Some(Value::Array(b)), Some(Value::Array(o)), Some(Value::Array(t)))
+            if key == "releases" =>
+        {
+            merge_releases(b, o, t).map(Value::Array).or(ours.cloned())
+        }
+        _ => ours.cloned(),
+    }
+}
+
+fn merge_objects(
+    base: &Map<String, Value>,
+    ours: &Map<String, Value>,
+    theirs: &Map<String, Value>,
+) -> Map<String, Value> {
+    let keys = ours
+        .keys()
+        .chain(theirs.keys().filter(|k| !ours.contains_key(*k)));
+
+    keys.filter_map(|k| {
+        merge_value(k, base.get(k), ours.get(k), theirs.get(k)).map(|v| (k.clone(), v))
+    })
+    .collect()
+}
+
+// In the order of ours, followed by releases only theirs has; None if a side
+// has releases without an artist and album, or two with the same ID, which
+// can't be told apart
+fn merge_releases(base: &[Value], ours: &[Value], theirs: &[Value]) -> Option<Vec<Value>> {
+    let (base, ours, theirs) = (by_id(base)?, by_id(ours)?, by_id(theirs)?);
+
+    let (base_map, ours_map, theirs_map) = (lookup(&base), lookup(&ours), lookup(&theirs));
+
+    let ids = ours.iter().chain(
+        theirs
+            .iter()
+            .filter(|(id, _)| !ours_map.contains_key(id.as_str())),
+    );
+
+    Some(
+        ids.filter_map(|(id, _)| {
+            let id = id.as_str();
+            merge_value(
+                "",
+                base_map.get(id).copied(),
+                ours_map.get(id).copied(),
+                theirs_map.get(id).copied(),
+            )
+        })
+        .collect(),
+    )
+}
+
+fn lookup<'a>(releases: &'a [(String, &'a Value)]) -> HashMap<&'a str, &'a Value> {
+    releases.iter().map(|(id, v)| (id.as_str(), *v)).collect()
+}
+
+fn by_id(releases: &[Value]) -> Option<Vec<(String, &Value)>> {
+    let mut seen = HashSet::new();
+    let mut keyed = vec![];
+
+    for value in releases {
+        let artist = value.get("artist")?.as_str()?;
+        let album = value.get("album")?.as_str()?;
+        let id = release::id_of(artist, album);
+
+        if !seen.insert(id.clone()) {
+            return None;
+        }
+
+        keyed.push((id, value));
+    }
+
+    Some(keyed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn release(artist: &str, triage: Option<&str>, genre: Option<&str>) -> Value {
+        json!({
+            "date": "2023-04-14",
+            "artist": artist,
+            "album": "Album",
+            "label": "Label",
+            "skip": false,
+            "skip_reasons": [],
+            "triage": triage,
+            "metallum": genre.map(|g| json!({ "genre": g })),
+        })
+    }
+
+    #[test]
+    fn one_side_changed() {
+        let base = json!({ "a": 1, "b": { "c": 2 }, "list": [1, 2] });
+        let ours = json!({ "a": 2, "b": { "c": 2 }, "list": [1, 2] });
+        let theirs = json!({ "a": 1, "b": { "c": 3 }, "list": [1, 2, 3], "d": true });
+
+        assert_eq!(
+            merge(&base, &ours, &theirs),
+            json!({ "a": 2, "b": { "c": 3 }, "list": [1, 2, 3], "d": true })
+        );
+    }
+
+    #[test]
+    fn both_changed_ours_wins() {
+        let base = json!({ "a": 1, "list": [1] });
+        let ours = json!({ "a": 2, "list": [2] });
+        let theirs = json!({ "a": 3, "list": [3] });
+
+        assert_eq!(merge(&base, &ours, &theirs), ours);
+    }
+
+    #[test]
+    fn releases_by_id() {
+        // A job enriched Enslaved and found a new release while the TUI marked
+        // Enslaved and the API skipped Opeth
+        let base = json!({ "releases": [
+            release("Enslaved", None, None),
+            release("Opeth", None, None),
+        ]});
+        let ours = json!({ "releases": [
+            release("Enslaved", None, Some("Black Metal")),
+            release("Opeth", None, None),
+            release("Gojira", None, None),
+        ]});
+        let theirs = json!({ "releases": [
+            release("Enslaved", Some("keep"), None),
+            release("Opeth", Some("skip"), None),
+        ]});
+
+        assert_eq!(
+            merge(&base, &ours, &theirs),
+            json!({ "releases": [
+                release("Enslaved", Some("keep"), Some("Black Metal")),
+                release("Opeth", Some("skip"), None),
+                release("Gojira", None, None),
+            ]})
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use crate::AppError;
+use chrono::{DateTime, Utc};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Request, Response, Server, StatusCode};
+use log::{error, info};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub const RELEASES_FETCHED: &str = "metalpal_releases_fetched_total";
+pub const PARSE_FAILURES: &str = "metalpal_parse_failures_total";
+pub const ENRICHMENT: &str = "metalpal_enrichment_total";
+pub const SKIPPED: &str = "metalpal_skipped_total";
+pub const NOTIFICATIONS: &str = "metalpal_notifications_total";
+pub const JOBS: &str = "metalpal_daemon_jobs_total";
+pub const DATE_CHANGES: &str = "metalpal_date_changes_total";
+
+const HELP: [(&str, &str); 7] = [
+    (RELEASES_FETCHED, "Releases fetched, by source"),
+    (
+        PARSE_FAILURES,
+        "Entries that could not be parsed, by source and kind",
+    ),
+    (
+        ENRICHMENT,
+        "Enrichment lookups, by source and result (hit, miss or error)",
+    ),
+    (SKIPPED, "Releases skipped, by reason"),
+    (
+        NOTIFICATIONS,
+        "Notifications, by target and result (sent or failed)",
+    ),
+    (JOBS, "Daemon jobs run, by job and result"),
+    (
+        DATE_CHANGES,
+        "Stored releases whose date changed on a fetch, by source",
+    ),
+];
+
+type Labels = Vec<(&'static str, String)>;
+
+// Counters since the process started; for one-shot runs that's the run
+static COUNTERS: Mutex<BTreeMap<(&'static str, Labels), u64>> = Mutex::new(BTreeMap::new());
+
+static REPORT: Mutex<Option<(PathBuf, DateTime<Utc>)>> = Mutex::new(None);
+
+pub fn inc(name: &'static str, labels: &[(&'static str, &str)]) {
+    add(name, labels, 1);
+}
+
+pub fn add(name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+    let labels = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+
+    if let Ok(mut counters) = COUNTERS.lock() {
+        *counters.entry((name, labels)).or_default() += value;
+    }
+}
+
+// Prometheus text exposition format
+pub fn render() -> String {
+    let counters = match COUNTERS.lock() {
+        Ok(counters) => counters,
+        Err(_) => return String::new(),
+    };
+
+    let mut out = String::new();
+
+    for (name, help) in HELP {
+        out += &format!("# HELP {} {}\n# TYPE {} counter\n", name, help, name);
+
+        for ((_, labels), value) in counters.iter().filter(|((n, _), _)| *n == name) {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+                .collect();
+
+            out += &format!("{}{{{}}} {}\n", name, labels.join(","), value);
+        }
+    }
+
+    out
+}
+
+// Label values escape backslashes, quotes and newlines
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Where the run report is written when the run ends
+pub fn report_to(path: PathBuf) {
+    if let Ok(mut report) = REPORT.lock() {
+        *report = Some((path, Utc::now()));
+    }
+}
+
+// Writes the run report, if one was asked for; `status` is "ok",
+// "no_releases", "scraper_broken" (exit code 2) or "error"
+pub fn write_report(status: &str, message: Option<&str>) {
+    let (path, started) = match REPORT.lock().ok().and_then(|r| r.clone()) {
+        Some(report) => report,
+        None => return,
+    };
+
+    let counters: Vec<_> = match COUNTERS.lock() {
+        Ok(counters) => counters
+            .iter()
+            .map(|((name, labels), value)| {
+                let labels: BTreeMap<&str, &str> =
+                    labels.iter().map(|(k, v)| (*k, v.as_str())).collect();
+
+                json!({ "name": name, "labels": labels, "value": value })
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    let report = json!({
+        "started": started,
+        "finished": Utc::now(),
+        "status": status,
+        "error": message,
+        "counters": counters,
+    });
+
+    let result = serde_json::to_string_pretty(&report)
+        .map_err(AppError::from)
+        .and_then(|json| std::fs::write(&path, json + "\n").map_err(AppError::from));
+
+    match result {
+        Ok(()) => info!("Wrote run report to '{}'", path.display()),
+        Err(e) => error!("Could not write run report to '{}': {}", path.display(), e),
+    }
+}
+
+// Serves the counters on /metrics for Prometheus to scrape
+pub async fn serve(addr: SocketAddr) -> Result<(), AppError> {
+    let make_svc = make_service_fn(|_conn| async {
+        Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+            let mut resp = Response::new(Body::empty());
+
+            if req.uri().path() != "/metrics" {
+                *resp.status_mut() = StatusCode::NOT_FOUND;
+                return Ok::<_, Infallible>(resp);
+            }
+
+            *resp.body_mut() = Body::from(render());
+            resp.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+
+            Ok(resp)
+        }))
+    });
+
+    info!("Serving metrics on http://{}/metrics", addr);
+
+    Server::try_bind(&addr)?.serve(make_svc).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_counters() {
+        // Counters are shared with every other test, so use targets of our own
+        inc(
+            NOTIFICATIONS,
+            &[("target", "render-test"), ("result", "sent")],
+        );
+        add(
+            NOTIFICATIONS,
+            &[("target", "render-test"), ("result", "sent")],
+            2,
+        );
+        inc(
+            NOTIFICATIONS,
+            &[("target", "say \"hi\"\\\n"), ("result", "failed")],
+        );
+
+        let out = render();
+
+        assert!(out.contains(
+            "# HELP metalpal_notifications_total Notifications, by target and result (sent or failed)\n\
+             # TYPE metalpal_notifications_total counter\n"
+        ));
+        assert!(out
+            .contains("metalpal_notifications_total{target=\"render-test\",result=\"sent\"} 3\n"));
+        assert!(out.contains(
+            "metalpal_notifications_total{target=\"say \\\"hi\\\"\\\\\\n\",result=\"failed\"} 1\n"
+        ));
+        // Every counter is declared, whether or not it has been counted
+        assert_eq!(out.matches("# TYPE ").count(), HELP.len());
+    }
+}
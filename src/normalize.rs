@@ -0,0 +1,190 @@
+use scraper::Html;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// Words in a trailing (...) or [...] that mark an edition of an album rather
+// than a different album, ie. "72 Seasons (Deluxe Edition)"
+const EDITION_WORDS: [&str; 9] = [
+    "deluxe",
+    "edition",
+    "remaster",
+    "remastered",
+    "reissue",
+    "expanded",
+    "anniversary",
+    "bonus",
+    "version",
+];
+
+// Trailing words some sources add to an artist name, ie. "The Ocean Collective"
+const ARTIST_SUFFIXES: [&str; 1] = ["collective"];
+
+// Key for comparing names from different sources: entities decoded, diacritics
+// folded, lowercase, "&" as "and", and punctuation and whitespace collapsed to
+// single spaces, ie. "Mötley Crüe" -> "motley crue"
+pub fn text(name: &str) -> String {
+    let name = if name.contains('&') {
+        decode_entities(name)
+    } else {
+        name.to_string()
+    };
+
+    let mut folded = String::new();
+
+    for c in name.nfkd().filter(|c| !is_combining_mark(*c)) {
+        match fold(c) {
+            Some(s) => folded.push_str(s),
+            None => folded.push(c),
+        }
+    }
+
+    folded
+        .to_lowercase()
+        .replace('&', " and ")
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// Like text(), minus "The" (leading, or trailing as in "Band, The") and
+// suffixes like "Collective"
+pub fn artist(name: &str) -> String {
+    let mut words: Vec<String> = text(name).split(' ').map(String::from).collect();
+
+    if words.len() > 1 && words[0] == "the" {
+        words.remove(0);
+    } else if words.len() > 1 && name.trim_end().to_lowercase().ends_with(", the") {
+        words.pop();
+    }
+
+    if words.len() > 1 && ARTIST_SUFFIXES.contains(&words[words.len() - 1].as_str()) {
+        words.pop();
+    }
+
+    words.join(" ")
+}
+
+// Like text(), minus edition suffixes like "(Deluxe)" or "- Remastered"
+pub fn album(name: &str) -> String {
+    let mut name = name.trim().to_string();
+
+    while let Some(stripped) = strip_edition(&name) {
+        name = stripped;
+    }
+
+    text(&name)
+}
+
+pub fn same_artist(a: &str, b: &str) -> bool {
+    artist(a) == artist(b)
+}
+
+pub fn same_album(a: &str, b: &str) -> bool {
+    album(a) == album(b)
+}
+
+fn decode_entities(s: &str) -> String {
+    Html::parse_fragment(s).root_element().text().collect()
+}
+
+// Letters that NFKD doesn't decompose
+fn fold(c: char) -> Option<&'static str> {
+    let folded = match c {
+        'ø' | 'Ø' => "o",
+        'æ' | 'Æ' => "ae",
+        'œ' | 'Œ' => "oe",
+        'ß' => "ss",
+        'ð' | 'Ð' | 'đ' | 'Đ' => "d",
+        'þ' | 'Þ' => "th",
+        'ł' | 'Ł' => "l",
+        'ı' => "i",
+        _ => return None,
+    };
+
+    Some(folded)
+}
+
+// Returns the name without its last edition suffix, if it has one
+fn strip_edition(name: &str) -> Option<String> {
+    let is_edition = |suffix: &str| {
+        text(suffix)
+            .split(' ')
+            .any(|word| EDITION_WORDS.contains(&word))
+    };
+
+    // "Album (Deluxe)" or "Album [Remastered 2023]"
+    for (open, close) in [('(', ')'), ('[', ']')] {
+        if let Some(rest) = name.strip_suffix(close) {
+            if let Some((album, suffix)) = rest.rsplit_once(open) {
+                if is_edition(suffix) && !album.trim().is_empty() {
+                    return Some(album.trim().to_string());
+                }
+            }
+        }
+    }
+
+    // "Album - Deluxe Edition"
+    for separator in [" - ", " – ", " — "] {
+        if let Some((album, suffix)) = name.rsplit_once(separator) {
+            if is_edition(suffix) && !album.trim().is_empty() {
+                return Some(album.trim().to_string());
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fold_diacritics() {
+        assert!(same_artist("Mötley Crüe", "Motley Crue"));
+        assert!(same_artist("Queensrÿche", "QUEENSRYCHE"));
+        assert!(same_artist("Mayhem", "Mayhem"));
+        assert_eq!(text("Ænima"), "aenima");
+        assert_eq!(text("Kvelertak – Endling"), "kvelertak endling");
+    }
+
+    #[test]
+    fn entities() {
+        assert!(same_artist("Coheed &amp; Cambria", "Coheed and Cambria"));
+        assert!(same_artist("Mot&ouml;rhead", "Motörhead"));
+        assert_eq!(text("Guns N&#39; Roses"), "guns n roses");
+    }
+
+    #[test]
+    fn artist_articles_and_suffixes() {
+        assert!(same_artist("The Ocean Collective", "The Ocean"));
+        assert!(same_artist(
+            "The Black Dahlia Murder",
+            "Black Dahlia Murder"
+        ));
+        assert!(!same_artist("The Ocean", "Ocean Collective Band"));
+        assert_eq!(artist("The The"), "the");
+        assert_eq!(artist("Collective"), "collective");
+    }
+
+    #[test]
+    fn album_editions() {
+        assert!(same_album("72 Seasons (Deluxe)", "72 Seasons"));
+        assert!(same_album(
+            "Master of Puppets [Remastered 2017]",
+            "Master of Puppets"
+        ));
+        assert!(same_album(
+            "Jane Doe - 20th Anniversary Edition",
+            "Jane Doe"
+        ));
+        assert!(same_album(
+            "Ride the Lightning (Deluxe) (Remastered)",
+            "Ride the Lightning"
+        ));
+        assert!(!same_album("The Wretched; The Ruinous", "The Wretched"));
+        assert_eq!(album("(Deluxe)"), "deluxe");
+        assert_eq!(album("Live (at Wacken)"), "live at wacken");
+    }
+}
@@ -0,0 +1,229 @@
+use crate::config::Release;
+use crate::notifier::{check_response, fields, header, Notice, Notifier};
+use crate::AppError;
+use async_trait::async_trait;
+use log::warn;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+// Discord allows at most 10 embeds per message
+const MAX_EMBEDS: usize = 10;
+
+// Keeps messages under Discord's 2000 character limit
+const MAX_LINES: usize = 15;
+
+const MAX_RETRIES: u32 = 3;
+
+pub struct Discord {
+    name: String,
+    client: Client,
+    webhook_url: String,
+}
+
+impl Discord {
+    pub fn new(name: String, client: Client, webhook_url: String) -> Self {
+        Self {
+            name,
+            client,
+            webhook_url,
+        }
+    }
+
+    // Posts a message, waiting out rate limits
+    async fn execute(&self, body: &Value) -> Result<(), AppError> {
+        let mut retries = 0;
+
+        loop {
+            let resp = self
+                .client
+                .post(&self.webhook_url)
+                .query(&[("wait", "true")])
+                .json(body)
+                .send()
+                .await?;
+
+            if resp.status() == StatusCode::TOO_MANY_REQUESTS && retries < MAX_RETRIES {
+                let retry_after = resp
+                    .json::<Value>()
+                    .await
+                    .ok()
+                    .and_then(|v| v["retry_after"].as_f64())
+                    .unwrap_or(1.0);
+
+                retries += 1;
+                warn!(
+                    "Rate limited by Discord; retrying in {}s ({}/{})",
+                    retry_after, retries, MAX_RETRIES
+                );
+                tokio::time::sleep(Duration::from_secs_f64(retry_after)).await;
+                continue;
+            }
+
+            check_response(&self.name, resp).await?;
+
+            return Ok(());
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for Discord {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    // A GET on a webhook URL returns the webhook if it exists
+    async fn preflight(&mut self) -> Result<(), AppError> {
+        let resp = self.client.get(&self.webhook_url).send().await?;
+        check_response(&self.name, resp).await?;
+
+        Ok(())
+    }
+
+    async fn notify(&self, releases: &[&Release]) -> Result<(), AppError> {
+        let embeds: Vec<Value> = releases
+            .iter()
+            .enumerate()
+            .map(|(i, release)| embed(i + 1, release))
+            .collect();
+
+        for (i, chunk) in embeds.chunks(MAX_EMBEDS).enumerate() {
+            let mut body = json!({ "embeds": chunk });
+
+            if i == 0 {
+                body["content"] = json!(format!(":metal: {}", header(releases)));
+            }
+
+            self.execute(&body).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn announce(&self, notices: &[&Notice]) -> Result<(), AppError> {
+        let lines: Vec<String> = notices
+            .iter()
+            .map(|n| format!("{} {}", n.emoji(), n.text))
+            .collect();
+
+        for chunk in lines.chunks(MAX_LINES) {
+            self.execute(&json!({ "content": chunk.join("\n") }))
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn embed(position: usize, release: &Release) -> Value {
+    // Discord rejects the whole message if a field's value is empty
+    let fields: Vec<Value> = fields(release)
+        .into_iter()
+        .filter(|(_, value)| !value.trim().is_empty())
+        .map(|(name, value)| json!({ "name": name, "value": value, "inline": true }))
+        .collect();
+
+    let mut embed = json!({
+        "title": format!("{}. {} - {}", position, release.artist, release.album),
+        "color": 0x36a64f,
+        "fields": fields,
+    });
+
+    if let Some(metallum) = &release.metallum {
+        if !metallum.url.is_empty() {
+            embed["url"] = json!(metallum.url);
+        }
+
+        if !metallum.band_img_url.is_empty() {
+            embed["thumbnail"] = json!({ "url": metallum.band_img_url });
+        }
+    }
+
+    embed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::MetallumArtistInfo;
+    use crate::notifier::stand_in;
+
+    fn discord(url: &str) -> Discord {
+        Discord::new(
+            "discord".to_string(),
+            Client::new(),
+            format!("{}/webhook", url),
+        )
+    }
+
+    #[tokio::test]
+    async fn notify() {
+        let server = stand_in::http(vec![]);
+
+        let releases: Vec<Release> = (1..=12)
+            .map(|i| {
+                let mut release = stand_in::release("Enslaved", &format!("Album {}", i));
+                release.metallum = Some(MetallumArtistInfo {
+                    country_origin: "Norway".to_string(),
+                    ..Default::default()
+                });
+                release
+            })
+            .collect();
+        let releases: Vec<&Release> = releases.iter().collect();
+
+        discord(&server.url).notify(&releases).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].uri, "/webhook?wait=true");
+        assert_eq!(
+            requests[0].body["content"],
+            ":metal: There are 12 releases today!"
+        );
+        assert_eq!(requests[0].body["embeds"].as_array().unwrap().len(), 10);
+        assert_eq!(requests[1].body["embeds"].as_array().unwrap().len(), 2);
+        assert!(requests[1].body.get("content").is_none());
+
+        // Empty genre and URL are left out
+        let embed = &requests[0].body["embeds"][0];
+        let fields: Vec<&str> = embed["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["name"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(embed["title"], "1. Enslaved - Album 1");
+        assert_eq!(fields, ["Release Date", "Country", "Label"]);
+        assert!(embed.get("url").is_none());
+    }
+
+    #[tokio::test]
+    async fn rate_limits_and_errors() {
+        let server = stand_in::http(vec![
+            (429, json!({ "retry_after": 0.01 })),
+            (200, json!({})),
+            (400, json!({ "message": "Invalid Form Body" })),
+        ]);
+        let discord = discord(&server.url);
+
+        let notice = Notice {
+            text: "Enslaved moved".to_string(),
+            release: stand_in::release("Enslaved", "Heimdal"),
+        };
+
+        // Waits out the rate limit and tries again
+        discord.announce(&[&notice]).await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(
+            server.requests()[1].body["content"],
+            ":calendar: Enslaved moved"
+        );
+
+        let err = discord.announce(&[&notice]).await.unwrap_err();
+        assert!(err.to_string().contains("400 Bad Request"));
+        assert!(err.to_string().contains("Invalid Form Body"));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::config::{Release, SmtpSecurity};
+use crate::notifier::{header, Notice, Notifier};
+use crate::{export, AppError};
+use async_trait::async_trait;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+pub struct Email {
+    name: String,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Email {
+    pub fn new(
+        name: String,
+        host: &str,
+        port: Option<u16>,
+        security: SmtpSecurity,
+        credentials: Option<(String, String)>,
+        from: &str,
+        to: &[String],
+    ) -> Result<Self, AppError> {
+        let mut builder = match security {
+            SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                host,
+            )),
+        }
+        .map_err(|e| AppError::NotifierError(format!("Invalid SMTP host '{}': {}", host, e)))?;
+
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        if to.is_empty() {
+            return Err(AppError::NotifierError(format!(
+                "Email notifier '{}' has no recipients",
+                name
+            )));
+        }
+
+        Ok(Self {
+            name,
+            transport: builder.build(),
+            from: mailbox(from)?,
+            to: to.iter().map(|t| mailbox(t)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    async fn send(&self, subject: String, body: MultiPart) -> Result<(), AppError> {
+        let mut builder = Message::builder().from(self.from.clone()).subject(subject);
+
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+
+        let message = builder
+            .multipart(body)
+            .map_err(|e| AppError::NotifierError(format!("Could not build email: {}", e)))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| AppError::NotifierError(format!("Could not send email: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, AppError> {
+    address
+        .parse()
+        .map_err(|e| AppError::NotifierError(format!("Invalid email address '{}': {}", address, e)))
+}
+
+#[async_trait(?Send)]
+impl Notifier for Email {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn preflight(&mut self) -> Result<(), AppError> {
+        self.transport.test_connection().await.map_err(|e| {
+            AppError::NotifierError(format!("Could not connect to SMTP server: {}", e))
+        })?;
+
+        Ok(())
+    }
+
+    async fn notify(&self, releases: &[&Release]) -> Result<(), AppError> {
+        let subject = format!(
+            "metalpal: {} releases on {}",
+            releases.len(),
+            chrono::Local::now().date_naive()
+        );
+
+        let plain: Vec<String> = releases
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                let url = r
+                    .metallum
+                    .as_ref()
+                    .filter(|m| !m.url.is_empty())
+                    .map(|m| format!(" <{}>", m.url))
+                    .unwrap_or_default();
+
+                format!("{}. {} - {}{}", i + 1, r.artist, r.album, url)
+            })
+            .collect();
+
+        self.send(
+            subject,
+            MultiPart::alternative_plain_html(
+                format!("{}\n\n{}\n", header(releases), plain.join("\n")),
+                export::html(&header(releases), releases),
+            ),
+        )
+        .await
+    }
+
+    async fn announce(&self, notices: &[&Notice]) -> Result<(), AppError> {
+        let subject = format!("metalpal: {} release updates", notices.len());
+        let plain: Vec<String> = notices.iter().map(|n| n.text.clone()).collect();
+
+        self.send(
+            subject,
+            MultiPart::alternative_plain_html(
+                plain.join("\n") + "\n",
+                format!(
+                    "<ul>{}</ul>",
+                    plain
+                        .iter()
+                        .map(|text| format!("<li>{}</li>", export::html_escape(text)))
+                        .collect::<String>()
+                ),
+            ),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::notifier::stand_in;
+
+    #[tokio::test]
+    async fn notify() {
+        let (port, messages) = stand_in::smtp().await;
+        let email = Email::new(
+            "email".to_string(),
+            "127.0.0.1",
+            Some(port),
+            SmtpSecurity::None,
+            None,
+            "metalpal@localhost",
+            &["me@localhost".to_string()],
+        )
+        .unwrap();
+
+        let releases = [
+            stand_in::release("Enslaved", "Heimdal"),
+            stand_in::release("Hooded Menace", "Lachrymose & Monuments"),
+        ];
+        let releases: Vec<&Release> = releases.iter().collect();
+
+        email.notify(&releases).await.unwrap();
+
+        let messages = messages.lock().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Subject: metalpal: 2 releases on"));
+        assert!(messages[0].contains("To: me@localhost"));
+        assert!(messages[0].contains("1. Enslaved - Heimdal"));
+        assert!(messages[0].contains("2. Hooded Menace - Lachrymose & Monuments"));
+    }
+}
@@ -0,0 +1,202 @@
+use crate::config::Release;
+use crate::export::html_escape;
+use crate::notifier::{check_response, header, Notice, Notifier};
+use crate::AppError;
+use async_trait::async_trait;
+use log::info;
+use reqwest::{Client, Url};
+use serde_json::{json, Value};
+
+pub struct Matrix {
+    name: String,
+    client: Client,
+    homeserver: Url,
+    access_token: String,
+    room_id: String,
+}
+
+impl Matrix {
+    pub fn new(
+        name: String,
+        client: Client,
+        homeserver: &str,
+        access_token: String,
+        room_id: String,
+    ) -> Result<Self, AppError> {
+        let homeserver = Url::parse(homeserver).map_err(|e| {
+            AppError::NotifierError(format!("Invalid homeserver '{}': {}", homeserver, e))
+        })?;
+
+        Ok(Self {
+            name,
+            client,
+            homeserver,
+            access_token,
+            room_id,
+        })
+    }
+
+    async fn send(&self, body: String, formatted_body: String) -> Result<(), AppError> {
+        // The transaction ID makes retries of the same request idempotent
+        let txn_id = format!("metalpal-{}", chrono::Utc::now().timestamp_nanos());
+
+        let resp = self
+            .client
+            .put(self.url(&["rooms", &self.room_id, "send", "m.room.message", &txn_id]))
+            .bearer_auth(&self.access_token)
+            .json(&json!({
+                "msgtype": "m.text",
+                "body": body,
+                "format": "org.matrix.custom.html",
+                "formatted_body": formatted_body,
+            }))
+            .send()
+            .await?;
+
+        check_response(&self.name, resp).await?;
+
+        Ok(())
+    }
+
+    // Client-server API URL; segments are percent-encoded (room IDs contain '!' and ':')
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.homeserver.clone();
+
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty()
+                .extend(["_matrix", "client", "v3"])
+                .extend(segments);
+        }
+
+        url
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for Matrix {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn preflight(&mut self) -> Result<(), AppError> {
+        let resp = self
+            .client
+            .get(self.url(&["account", "whoami"]))
+            .bearer_auth(&self.access_token)
+            .send()
+            .await?;
+
+        let whoami: Value = check_response(&self.name, resp).await?.json().await?;
+
+        info!(
+            "Authenticated to Matrix as '{}'",
+            whoami["user_id"].as_str().unwrap_or_default()
+        );
+
+        Ok(())
+    }
+
+    async fn notify(&self, releases: &[&Release]) -> Result<(), AppError> {
+        let mut body = vec![header(releases)];
+        let mut formatted = vec![format!("<h4>{}</h4>", html_escape(&header(releases)))];
+
+        formatted.push("<ol>".to_string());
+
+        for (i, release) in releases.iter().enumerate() {
+            let name = format!("{} - {}", release.artist, release.album);
+            // Metal Archives may not have a genre or page for the band
+            let genre = release
+                .metallum
+                .as_ref()
+                .filter(|m| !m.genre.trim().is_empty())
+                .map(|m| format!(" ({})", m.genre))
+                .unwrap_or_default();
+
+            body.push(format!("{}. {}{}", i + 1, name, genre));
+
+            let name = match release.metallum.as_ref().filter(|m| !m.url.is_empty()) {
+                Some(m) => format!(
+                    "<a href=\"{}\">{}</a>",
+                    html_escape(&m.url),
+                    html_escape(&name)
+                ),
+                None => html_escape(&name),
+            };
+
+            formatted.push(format!("<li>{}{}</li>", name, html_escape(&genre)));
+        }
+
+        formatted.push("</ol>".to_string());
+
+        self.send(body.join("\n"), formatted.join("")).await
+    }
+
+    async fn announce(&self, notices: &[&Notice]) -> Result<(), AppError> {
+        let body: Vec<String> = notices.iter().map(|n| n.text.clone()).collect();
+        let formatted: Vec<String> = body
+            .iter()
+            .map(|text| format!("<li>{}</li>", html_escape(text)))
+            .collect();
+
+        self.send(body.join("\n"), format!("<ul>{}</ul>", formatted.join("")))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::MetallumArtistInfo;
+    use crate::notifier::stand_in;
+
+    #[tokio::test]
+    async fn notify() {
+        let server = stand_in::http(vec![(200, json!({ "user_id": "@metalpal:localhost" }))]);
+        let mut matrix = Matrix::new(
+            "matrix".to_string(),
+            Client::new(),
+            &server.url,
+            "token".to_string(),
+            "!room:localhost".to_string(),
+        )
+        .unwrap();
+
+        matrix.preflight().await.unwrap();
+
+        let release = stand_in::release("Hooded Menace", "Lachrymose & Monuments");
+        let mut unknown = stand_in::release("Enslaved", "Heimdal");
+        unknown.metallum = Some(Default::default());
+        let mut known = stand_in::release("Opeth", "Blackwater Park");
+        known.metallum = Some(MetallumArtistInfo {
+            genre: "Progressive Death Metal".to_string(),
+            url: "https://www.metal-archives.com/bands/Opeth/755".to_string(),
+            ..Default::default()
+        });
+
+        matrix.notify(&[&release, &unknown, &known]).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].uri, "/_matrix/client/v3/account/whoami");
+        assert_eq!(requests[0].headers["authorization"], "Bearer token");
+
+        assert_eq!(requests[1].method, "PUT");
+        assert!(requests[1]
+            .uri
+            .starts_with("/_matrix/client/v3/rooms/!room:localhost/send/m.room.message/metalpal-"));
+        assert_eq!(
+            requests[1].body["body"],
+            "There are 3 releases today!\n\
+             1. Hooded Menace - Lachrymose & Monuments\n\
+             2. Enslaved - Heimdal\n\
+             3. Opeth - Blackwater Park (Progressive Death Metal)"
+        );
+        assert!(requests[1].body["formatted_body"]
+            .as_str()
+            .unwrap()
+            .contains(
+                "<li>Hooded Menace - Lachrymose &amp; Monuments</li>\
+                 <li>Enslaved - Heimdal</li>\
+                 <li><a href=\"https://www.metal-archives.com/bands/Opeth/755\">Opeth - Blackwater Park</a> (Progressive Death Metal)</li>"
+            ));
+    }
+}
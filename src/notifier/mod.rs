@@ -0,0 +1,409 @@
+mod discord;
+mod email;
+mod matrix;
+#[cfg(test)]
+mod stand_in;
+mod webhook;
+
+use crate::config::{Config, FilterProfile, NotifierBackend, Release};
+use crate::{metrics, subscription, AppError};
+use async_trait::async_trait;
+use log::{error, info};
+use serde::Serialize;
+
+// News about a release outside of the daily digest, ie. a postponement
+#[derive(Serialize, Debug, Clone)]
+pub struct Notice {
+    pub text: String,
+    pub release: Release,
+}
+
+impl Notice {
+    // Shortcode for chat backends; releases by followed artists stand out
+    pub fn emoji(&self) -> &'static str {
+        if self.release.followed {
+            ":star:"
+        } else {
+            ":calendar:"
+        }
+    }
+}
+
+// A place releases can be delivered to. Futures aren't Send because AppError
+// can hold a scraper selector error
+#[async_trait(?Send)]
+pub trait Notifier {
+    fn name(&self) -> &str;
+
+    // Checks the target is usable before any work is done
+    async fn preflight(&mut self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn notify(&self, releases: &[&Release]) -> Result<(), AppError>;
+
+    async fn announce(&self, notices: &[&Notice]) -> Result<(), AppError>;
+}
+
+// Only passes on releases matching the target's filter
+struct Filtered {
+    filter: FilterProfile,
+    inner: Box<dyn Notifier>,
+}
+
+#[async_trait(?Send)]
+impl Notifier for Filtered {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn preflight(&mut self) -> Result<(), AppError> {
+        self.inner.preflight().await
+    }
+
+    async fn notify(&self, releases: &[&Release]) -> Result<(), AppError> {
+        let matching: Vec<&Release> = releases
+            .iter()
+            .filter(|r| subscription::matches(&self.filter, r))
+            .copied()
+            .collect();
+
+        if matching.is_empty() {
+            info!("No releases today for notifier '{}'", self.name());
+            return Ok(());
+        }
+
+        self.inner.notify(&matching).await
+    }
+
+    async fn announce(&self, notices: &[&Notice]) -> Result<(), AppError> {
+        let matching: Vec<&Notice> = notices
+            .iter()
+            .filter(|n| subscription::matches(&self.filter, &n.release))
+            .copied()
+            .collect();
+
+        if matching.is_empty() {
+            return Ok(());
+        }
+
+        self.inner.announce(&matching).await
+    }
+}
+
+// Builds the notifiers configured in `notifiers` (Slack is set up separately)
+pub fn from_config(config: &Config) -> Result<Vec<Box<dyn Notifier>>, AppError> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("metalpal/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+
+    config
+        .notifiers
+        .iter()
+        .map(|target| {
+            let name = target.name.clone();
+
+            let inner: Box<dyn Notifier> = match &target.backend {
+                NotifierBackend::Discord { webhook_url } => Box::new(discord::Discord::new(
+                    name,
+                    client.clone(),
+                    webhook_url.clone(),
+                )),
+                NotifierBackend::Matrix {
+                    homeserver,
+                    access_token,
+                    room_id,
+                } => Box::new(matrix::Matrix::new(
+                    name,
+                    client.clone(),
+                    homeserver,
+                    access_token.clone(),
+                    room_id.clone(),
+                )?),
+                NotifierBackend::Email {
+                    host,
+                    port,
+                    security,
+                    username,
+                    password,
+                    from,
+                    to,
+                } => Box::new(email::Email::new(
+                    name,
+                    host,
+                    *port,
+                    *security,
+                    username.clone().zip(password.clone()),
+                    from,
+                    to,
+                )?),
+                NotifierBackend::Webhook { url, headers } => Box::new(webhook::Webhook::new(
+                    name,
+                    client.clone(),
+                    url.clone(),
+                    headers.clone(),
+                )),
+            };
+
+            Ok(Box::new(Filtered {
+                filter: target.filter.clone(),
+                inner,
+            }) as Box<dyn Notifier>)
+        })
+        .collect()
+}
+
+// Delivers to every notifier; one failing target doesn't keep the others
+// from being notified. No target is sent an empty digest
+pub async fn notify_all(
+    notifiers: &[&dyn Notifier],
+    releases: &[&Release],
+) -> Result<(), AppError> {
+    if releases.is_empty() {
+        info!("No releases today; nothing to send");
+        return Ok(());
+    }
+
+    let mut failed = vec![];
+
+    for notifier in notifiers {
+        info!(
+            "Sending {} releases to '{}'",
+            releases.len(),
+            notifier.name()
+        );
+
+        if let Err(e) = notifier.notify(releases).await {
+            error!("Could not notify '{}': {}", notifier.name(), e);
+            failed.push(notifier.name().to_string());
+            metrics::inc(
+                metrics::NOTIFICATIONS,
+                &[("target", notifier.name()), ("result", "failed")],
+            );
+        } else {
+            metrics::inc(
+                metrics::NOTIFICATIONS,
+                &[("target", notifier.name()), ("result", "sent")],
+            );
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(AppError::NotifierError(format!(
+            "Could not notify: {}",
+            failed.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+// Like notify_all(), for notices
+pub async fn announce_all(
+    notifiers: &[&dyn Notifier],
+    notices: &[&Notice],
+) -> Result<(), AppError> {
+    if notices.is_empty() {
+        return Ok(());
+    }
+
+    let mut failed = vec![];
+
+    for notifier in notifiers {
+        info!("Sending {} notices to '{}'", notices.len(), notifier.name());
+
+        if let Err(e) = notifier.announce(notices).await {
+            error!("Could not send notices to '{}': {}", notifier.name(), e);
+            failed.push(notifier.name().to_string());
+            metrics::inc(
+                metrics::NOTIFICATIONS,
+                &[("target", notifier.name()), ("result", "failed")],
+            );
+        } else {
+            metrics::inc(
+                metrics::NOTIFICATIONS,
+                &[("target", notifier.name()), ("result", "sent")],
+            );
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(AppError::NotifierError(format!(
+            "Could not send notices: {}",
+            failed.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn header(releases: &[&Release]) -> String {
+    format!("There are {} releases today!", releases.len())
+}
+
+// Label/value pairs describing a release, in display order
+pub fn fields(release: &Release) -> Vec<(&'static str, String)> {
+    let mut fields = vec![("Release Date", release.date.to_string())];
+
+    if let Some(metallum) = &release.metallum {
+        fields.push(("Genres", metallum.genre.clone()));
+        fields.push(("Country", metallum.country_origin.clone()));
+    }
+
+    if !release.label.is_empty() {
+        fields.push(("Label", release.label.clone()));
+    }
+
+    if let Some(spotify) = &release.spotify {
+        fields.push(("Spotify Popularity", spotify.popularity.to_string()));
+        fields.push(("Spotify Followers", spotify.followers.to_string()));
+    }
+
+    fields
+}
+
+// Fails with the response body for non-2xx responses
+async fn check_response(
+    name: &str,
+    resp: reqwest::Response,
+) -> Result<reqwest::Response, AppError> {
+    if resp.status().is_success() {
+        return Ok(resp);
+    }
+
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_default();
+
+    Err(AppError::NotifierError(format!(
+        "'{}' responded with {}: {}",
+        name, status, body
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Remembers what it was told; fails when asked to
+    struct Recorder {
+        name: String,
+        fail: bool,
+        notified: Rc<RefCell<Vec<String>>>,
+        announced: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Recorder {
+        fn new(name: &str, fail: bool) -> Self {
+            Self {
+                name: name.to_string(),
+                fail,
+                notified: Rc::default(),
+                announced: Rc::default(),
+            }
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl Notifier for Recorder {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn notify(&self, releases: &[&Release]) -> Result<(), AppError> {
+            self.notified
+                .borrow_mut()
+                .extend(releases.iter().map(|r| r.artist.clone()));
+
+            Ok(())
+        }
+
+        async fn announce(&self, notices: &[&Notice]) -> Result<(), AppError> {
+            if self.fail {
+                return Err(AppError::NotifierError("down".to_string()));
+            }
+
+            self.announced
+                .borrow_mut()
+                .extend(notices.iter().map(|n| n.text.clone()));
+
+            Ok(())
+        }
+    }
+
+    fn notice(artist: &str, label: &str) -> Notice {
+        let release = serde_json::from_value(serde_json::json!({
+            "date": "2023-04-14",
+            "artist": artist,
+            "album": "Album",
+            "label": label,
+            "skip_reasons": [],
+        }))
+        .unwrap();
+
+        Notice {
+            text: format!("{} moved", artist),
+            release,
+        }
+    }
+
+    #[tokio::test]
+    async fn filtered_announce() {
+        let notices = [
+            notice("Enslaved", "Nuclear Blast"),
+            notice("Opeth", "Reigning Phoenix"),
+        ];
+        let notices: Vec<&Notice> = notices.iter().collect();
+
+        let recorder = Recorder::new("recorder", false);
+        let announced = recorder.announced.clone();
+
+        let filtered = Filtered {
+            filter: FilterProfile {
+                labels: vec!["Nuclear Blast".to_string()],
+                ..Default::default()
+            },
+            inner: Box::new(recorder),
+        };
+
+        filtered.announce(&notices).await.unwrap();
+        // Nothing matching isn't an error, and nothing is sent
+        filtered.announce(&notices[1..]).await.unwrap();
+
+        assert_eq!(*announced.borrow(), vec!["Enslaved moved"]);
+    }
+
+    #[tokio::test]
+    async fn announce_all_keeps_going() {
+        let notices = [notice("Enslaved", "Nuclear Blast")];
+        let notices: Vec<&Notice> = notices.iter().collect();
+
+        let (down, up) = (Recorder::new("down", true), Recorder::new("up", false));
+
+        let err = announce_all(&[&down, &up], &notices).await.unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Notifier error: Could not send notices: down"
+        );
+        assert_eq!(*up.announced.borrow(), vec!["Enslaved moved"]);
+    }
+
+    #[tokio::test]
+    async fn nothing_to_send() {
+        let recorder = Recorder::new("recorder", false);
+
+        // No target gets an empty digest
+        notify_all(&[&recorder], &[]).await.unwrap();
+        announce_all(&[&recorder], &[]).await.unwrap();
+
+        assert!(recorder.notified.borrow().is_empty());
+        assert!(recorder.announced.borrow().is_empty());
+
+        let release = stand_in::release("Enslaved", "Heimdal");
+        notify_all(&[&recorder], &[&release]).await.unwrap();
+
+        assert_eq!(*recorder.notified.borrow(), vec!["Enslaved"]);
+    }
+}
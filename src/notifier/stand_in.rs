@@ -0,0 +1,155 @@
+// Local servers standing in for notifier APIs in tests
+use crate::config::Release;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    // Path and query
+    pub uri: String,
+    // Header names are lowercase
+    pub headers: HashMap<String, String>,
+    pub body: Value,
+}
+
+pub struct Http {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl Http {
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+// Records every request and answers with `responses` in order, then with 200
+// and an empty object
+pub fn http(responses: Vec<(u16, Value)>) -> Http {
+    let requests = Arc::new(Mutex::new(vec![]));
+    let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+
+    let make_svc = {
+        let requests = requests.clone();
+
+        make_service_fn(move |_conn| {
+            let (requests, responses) = (requests.clone(), responses.clone());
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    record(req, requests.clone(), responses.clone())
+                }))
+            }
+        })
+    };
+
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    Http { url, requests }
+}
+
+async fn record(
+    req: Request<Body>,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+    responses: Arc<Mutex<VecDeque<(u16, Value)>>>,
+) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+
+    requests.lock().unwrap().push(Recorded {
+        method: parts.method.to_string(),
+        uri: parts.uri.to_string(),
+        headers: parts
+            .headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or_default().to_string()))
+            .collect(),
+        body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+    });
+
+    let (status, body) = responses
+        .lock()
+        .unwrap()
+        .pop_front()
+        .unwrap_or((200, json!({})));
+
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap())
+}
+
+// Accepts mail on a free local port without authentication; returns the port
+// and the DATA of every message received
+pub async fn smtp() -> (u16, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let messages = Arc::new(Mutex::new(vec![]));
+
+    let received = messages.clone();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let received = received.clone();
+
+            tokio::spawn(async move {
+                let (read, mut write) = stream.into_split();
+                let mut lines = BufReader::new(read).lines();
+                let mut data: Option<Vec<String>> = None;
+
+                write.write_all(b"220 localhost ESMTP\r\n").await.ok();
+
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let reply: &[u8] = match &mut data {
+                        Some(message) if line == "." => {
+                            received.lock().unwrap().push(message.join("\n"));
+                            data = None;
+                            b"250 OK\r\n"
+                        }
+                        Some(message) => {
+                            message.push(line);
+                            continue;
+                        }
+                        None => match line.get(..4).unwrap_or_default().to_uppercase().as_str() {
+                            "EHLO" | "HELO" => b"250 localhost\r\n",
+                            "DATA" => {
+                                data = Some(vec![]);
+                                b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                            }
+                            "QUIT" => {
+                                write.write_all(b"221 Bye\r\n").await.ok();
+                                break;
+                            }
+                            _ => b"250 OK\r\n",
+                        },
+                    };
+
+                    write.write_all(reply).await.ok();
+                }
+            });
+        }
+    });
+
+    (port, messages)
+}
+
+pub fn release(artist: &str, album: &str) -> Release {
+    serde_json::from_value(json!({
+        "date": "2023-04-14",
+        "artist": artist,
+        "album": album,
+        "label": "Nuclear Blast",
+        "skip_reasons": [],
+    }))
+    .unwrap()
+}
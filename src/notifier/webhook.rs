@@ -0,0 +1,101 @@
+use crate::config::Release;
+use crate::notifier::{check_response, Notice, Notifier};
+use crate::AppError;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+pub struct Webhook {
+    name: String,
+    client: Client,
+    url: String,
+    headers: BTreeMap<String, String>,
+}
+
+impl Webhook {
+    pub fn new(
+        name: String,
+        client: Client,
+        url: String,
+        headers: BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            name,
+            client,
+            url,
+            headers,
+        }
+    }
+
+    async fn send(&self, body: Value) -> Result<(), AppError> {
+        let mut request = self.client.post(&self.url).json(&body);
+
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        check_response(&self.name, request.send().await?).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Releases are sent as stored in ~/.metalpal.json
+    async fn notify(&self, releases: &[&Release]) -> Result<(), AppError> {
+        self.send(json!({
+            "date": chrono::Local::now().date_naive(),
+            "count": releases.len(),
+            "releases": releases,
+        }))
+        .await
+    }
+
+    // Notices carry their text and the release it's about
+    async fn announce(&self, notices: &[&Notice]) -> Result<(), AppError> {
+        self.send(json!({
+            "date": chrono::Local::now().date_naive(),
+            "count": notices.len(),
+            "notices": notices,
+        }))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::notifier::stand_in;
+
+    #[tokio::test]
+    async fn notify() {
+        let server = stand_in::http(vec![(500, json!({ "error": "down" }))]);
+        let webhook = Webhook::new(
+            "webhook".to_string(),
+            Client::new(),
+            format!("{}/hook", server.url),
+            BTreeMap::from([("X-Token".to_string(), "secret".to_string())]),
+        );
+
+        let release = stand_in::release("Enslaved", "Heimdal");
+
+        let err = webhook.notify(&[&release]).await.unwrap_err();
+        assert!(err.to_string().contains("500 Internal Server Error"));
+
+        webhook.notify(&[&release]).await.unwrap();
+
+        let request = &server.requests()[1];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.uri, "/hook");
+        assert_eq!(request.headers["x-token"], "secret");
+        assert_eq!(request.body["count"], 1);
+        assert_eq!(request.body["releases"][0]["artist"], "Enslaved");
+        assert_eq!(request.body["releases"][0]["album"], "Heimdal");
+    }
+}
@@ -0,0 +1,293 @@
+use crate::config::{self, Config, Release};
+use crate::notifier::{self, Notice, Notifier};
+use crate::{feedback, follow, release, site, slack, AppError};
+use log::{debug, error, info};
+
+// Where releases get delivered: Slack plus any configured notifiers
+pub struct Targets {
+    pub slack: Option<slack::Slack>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    // Client for scraper_alert_channel, if configured
+    alerts: Option<slack::Slack>,
+}
+
+impl Targets {
+    // Checks every target up front so a bad channel or token is reported before
+    // any work is done
+    pub async fn setup(config: &Config, disable_slack: bool) -> Result<Self, AppError> {
+        let slack = if !disable_slack
+            && (!config.slack_channels.is_empty() || !config.subscriptions.is_empty())
+            && !config.slack_bot_token.is_empty()
+        {
+            let mut slack_client = slack::Slack::new(config);
+            slack_client.preflight().await?;
+
+            Some(slack_client)
+        } else {
+            None
+        };
+
+        let mut notifiers = notifier::from_config(config)?;
+
+        for notifier in notifiers.iter_mut() {
+            notifier.preflight().await.map_err(|e| {
+                AppError::NotifierError(format!(
+                    "Notifier '{}' failed preflight: {}",
+                    notifier.name(),
+                    e
+                ))
+            })?;
+        }
+
+        let alerts = (!disable_slack
+            && config.scraper_alert_channel.is_some()
+            && !config.slack_bot_token.is_empty())
+        .then(|| slack::Slack::new(config));
+
+        Ok(Self {
+            slack,
+            notifiers,
+            alerts,
+        })
+    }
+
+    // Tells the admin channel the scraper looks broken; failing to do so is only logged
+    pub async fn alert_scraper(&self, config: &Config, e: &AppError) {
+        let (slack_client, channel) = match (&self.alerts, &config.scraper_alert_channel) {
+            (Some(slack_client), Some(channel)) => (slack_client, channel),
+            _ => return,
+        };
+
+        let text = format!(":rotating_light: metalpal: {}", e);
+
+        if let Err(e) = slack_client.post_alert(channel, &text).await {
+            error!("Could not send scraper alert to '{}': {}", channel, e);
+        }
+    }
+
+    // Learns from reactions on previously posted releases
+    pub async fn collect_feedback(&self, config: &mut Config) -> Result<(), AppError> {
+        if let (Some(slack_client), true) = (&self.slack, config.slack_feedback) {
+            feedback::collect(config, slack_client).await?;
+            config::save_config(config)?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn weekly_export(&self, config: &mut Config) -> Result<(), AppError> {
+        if let Some(slack_client) = &self.slack {
+            if slack_client.weekly_export_due(config.slack_last_weekly_export)? {
+                slack_client.post_weekly_export(&config.releases).await?;
+
+                config.slack_last_weekly_export = Some(chrono::Local::now().date_naive());
+                config::save_config(config)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Slack first, then every other configured target
+    fn all(&self) -> Vec<&dyn Notifier> {
+        let mut targets: Vec<&dyn Notifier> = vec![];
+
+        if let Some(slack_client) = &self.slack {
+            targets.push(slack_client);
+        }
+
+        targets.extend(self.notifiers.iter().map(|n| n.as_ref()));
+
+        targets
+    }
+
+    // Sends the releases that haven't been sent yet to Slack and every other
+    // configured target, and marks them as sent. A target that fails doesn't get
+    // them again later; the others would get them twice.
+    pub async fn notify(&self, config: &mut Config, releases: &[&Release]) -> Result<(), AppError> {
+        let pending: Vec<&Release> = releases.iter().filter(|r| !r.notified).copied().collect();
+
+        if pending.is_empty() {
+            if !releases.is_empty() {
+                info!("Today's releases have already been sent");
+            }
+
+            return Ok(());
+        }
+
+        let result = notifier::notify_all(&self.all(), &pending).await;
+
+        for stored in &mut config.releases {
+            if pending
+                .iter()
+                .any(|r| r.date == stored.date && release::same(r, stored))
+            {
+                stored.notified = true;
+            }
+        }
+
+        // Remember posted messages to read reactions on them next time
+        if let (Some(slack_client), true) = (&self.slack, config.slack_feedback) {
+            config.posted_messages.extend(slack_client.take_posted());
+        }
+
+        config::save_config(config)?;
+
+        result
+    }
+
+    // Tells every target about the notices from fetch()
+    pub async fn announce(&self, notices: &[Notice]) -> Result<(), AppError> {
+        let notices: Vec<&Notice> = notices.iter().collect();
+
+        notifier::announce_all(&self.all(), &notices).await
+    }
+}
+
+// Merges a fresh fetch of the upcoming releases page into the release list and
+// returns notices about date changes (if asked for) and new releases by followed
+// artists; a fetch that fails the scraper checks is not saved
+pub async fn fetch(config: &mut Config) -> Result<Vec<Notice>, AppError> {
+    let (releases, stats) = release::fetch_releases().await?;
+
+    debug!("Scrape stats: {:?}", stats);
+    release::check_scrape(config, &releases, &stats)?;
+
+    debug!("Fetched {} releases", releases.len());
+    config.last_update = chrono::Utc::now();
+    let (releases, mut notices) =
+        release::merge_fetched(std::mem::take(&mut config.releases), releases);
+    config.releases = releases;
+
+    if !config.notify_date_changes {
+        notices.clear();
+    }
+
+    release::name_follows(config).await;
+    follow::mark(&config.follows, &mut config.releases);
+    notices.extend(follow::announce(&mut config.releases));
+
+    config::save_config(config)?;
+
+    Ok(notices)
+}
+
+// Enriches and filters the given releases, then merges them into the release store
+pub async fn enrich(config: &mut Config, releases: &mut Vec<Release>) -> Result<(), AppError> {
+    info!("Enriching {} releases", releases.len());
+
+    // Follows by Spotify or Metal Archives ID match once enriched, so releases
+    // are checked again after each lookup
+    follow::mark(&config.follows, releases);
+
+    // Enrich with Spotify metadata first; it's used to filter out most releases
+    release::enrich_with_spotify(
+        config.spotify_client_id.clone(),
+        config.spotify_client_secret.clone(),
+        releases,
+    )
+    .await?;
+
+    follow::mark(&config.follows, releases);
+    release::set_skip_spotify(config, releases);
+
+    // Enrich matching releases with metallum metadata
+    release::enrich_with_metallum(releases).await?;
+
+    follow::mark(&config.follows, releases);
+    release::set_skip_metallum(config, releases);
+
+    // Suppress releases similar to ones that got negative feedback
+    release::set_skip_feedback(config, releases);
+
+    // Merge releases into existing releases and save again
+    release::merge_releases(&mut config.releases, releases);
+    config::save_config(config)?;
+
+    if let Some(dir) = &config.site_dir {
+        site::generate(config, std::path::Path::new(dir))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_trait::async_trait;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Counter {
+        sent: Rc<RefCell<Vec<String>>>,
+    }
+
+    #[async_trait(?Send)]
+    impl Notifier for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        async fn notify(&self, releases: &[&Release]) -> Result<(), AppError> {
+            self.sent
+                .borrow_mut()
+                .extend(releases.iter().map(|r| r.artist.clone()));
+
+            Ok(())
+        }
+
+        async fn announce(&self, _notices: &[&Notice]) -> Result<(), AppError> {
+            Ok(())
+        }
+    }
+
+    fn release(artist: &str) -> Release {
+        serde_json::from_value(serde_json::json!({
+            "date": chrono::Local::now().date_naive(),
+            "artist": artist,
+            "album": "Album",
+            "label": "",
+            "skip_reasons": [],
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn releases_are_sent_once() {
+        let path =
+            std::env::temp_dir().join(format!("metalpal-notified-{}.json", std::process::id()));
+        let mut config = Config {
+            full_path: path.display().to_string(),
+            releases: vec![release("Enslaved"), release("Opeth")],
+            ..Default::default()
+        };
+
+        let counter = Counter::default();
+        let sent = counter.sent.clone();
+        let targets = Targets {
+            slack: None,
+            notifiers: vec![Box::new(counter)],
+            alerts: None,
+        };
+
+        // As at two notify times on the same day; Opeth was enriched in between
+        for artists in [&["Enslaved"][..], &["Enslaved", "Opeth"]] {
+            let today: Vec<Release> = release::get_releases_today(&config.releases)
+                .into_iter()
+                .filter(|r| artists.contains(&r.artist.as_str()))
+                .collect();
+            let today: Vec<&Release> = today.iter().collect();
+
+            targets.notify(&mut config, &today).await.unwrap();
+        }
+
+        assert_eq!(*sent.borrow(), vec!["Enslaved", "Opeth"]);
+
+        let stored: Config =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(stored.releases.iter().all(|r| r.notified));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
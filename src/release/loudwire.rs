@@ -0,0 +1,433 @@
+use super::{ScrapeStats, ENTRY_SELECTOR, SOURCE};
+use crate::config::Release;
+use crate::{metrics, AppError};
+use chrono::NaiveDate;
+use scraper::{ElementRef, Html, Node, Selector};
+
+// Separates the artists of a split release or collaboration
+const ARTIST_SEPARATOR: &str = " / ";
+
+// Separate the artist from the album in plain text entries
+const SEPARATORS: [&str; 3] = [" - ", " – ", " — "];
+
+// A line of a calendar paragraph, up to the next <br>
+#[derive(Debug, Default)]
+struct Line {
+    // Text of a <strong> at the start of the line; a date heading, or a
+    // bolded artist if the line is an entry
+    heading: Option<String>,
+    // Text before the album, ie. "Artist - "
+    before: String,
+    // Text of the first <em>
+    album: Option<String>,
+    // Text after the album, ie. " (Label)"
+    after: String,
+}
+
+impl Line {
+    fn is_empty(&self) -> bool {
+        self.heading.is_none()
+            && self.before.trim().is_empty()
+            && self.album.is_none()
+            && self.after.trim().is_empty()
+    }
+
+    fn push(&mut self, text: &str) {
+        match self.album {
+            Some(_) => self.after.push_str(text),
+            None => self.before.push_str(text),
+        }
+    }
+}
+
+// Parses the release calendar. Each paragraph holds one or more days: a date
+// heading in <strong>, then one release per line, ie.
+// `Artist - <em>Album</em> (Label)<br>`. Paragraphs without a heading (intros,
+// ads) are ignored.
+pub fn parse(html: &str) -> Result<(Vec<Release>, ScrapeStats), AppError> {
+    let document = Html::parse_document(html);
+    let selector = Selector::parse(ENTRY_SELECTOR)?;
+
+    let mut releases = Vec::new();
+    let mut stats = ScrapeStats::default();
+
+    for paragraph in document.select(&selector) {
+        stats.paragraphs += 1;
+
+        let mut lines = vec![];
+        let mut line = Line::default();
+
+        walk(paragraph, &mut line, &mut lines);
+        lines.push(line);
+
+        // Entries before the first heading, or under one that can't be parsed,
+        // have no date
+        let mut date = None;
+
+        for mut line in lines.into_iter().filter(|l| !l.is_empty()) {
+            if let Some(heading) = line.heading.take() {
+                match parse_date(&heading) {
+                    Some(heading) => {
+                        stats.days += 1;
+                        date = Some(heading);
+                    }
+                    // A bold line that isn't an entry is a heading; what's under
+                    // it has no date rather than the one before it
+                    None if line.album.is_none()
+                        && !SEPARATORS.iter().any(|s| line.before.contains(s)) =>
+                    {
+                        stats.days += 1;
+                        stats.failures += 1;
+                        metrics::inc(
+                            metrics::PARSE_FAILURES,
+                            &[("source", SOURCE), ("kind", "date")],
+                        );
+
+                        date = None;
+                        continue;
+                    }
+                    // ie. a bolded artist
+                    None => line.before.insert_str(0, &heading),
+                }
+
+                // The first release may follow the heading without a <br>
+                if line.before.trim().is_empty() && line.album.is_none() {
+                    continue;
+                }
+            }
+
+            let date = match date {
+                Some(date) => date,
+                None => continue,
+            };
+
+            match parse_entry(&line) {
+                Some((artists, album, label)) => {
+                    releases.extend(artists.into_iter().map(|artist| Release {
+                        date,
+                        artist,
+                        album: album.clone(),
+                        label: label.clone(),
+                        spotify: None,
+                        metallum: None,
+                        skip: false,
+                        skip_reasons: vec![],
+                        updated: None,
+                        triage: None,
+                        status: None,
+                        date_history: vec![],
+                        followed: false,
+                        announced: false,
+                        looked_up: false,
+                        notified: false,
+                    }));
+                }
+                None => {
+                    stats.failures += 1;
+                    metrics::inc(
+                        metrics::PARSE_FAILURES,
+                        &[("source", SOURCE), ("kind", "entry")],
+                    );
+                }
+            }
+        }
+    }
+
+    releases.sort_by_key(|r| r.date);
+
+    Ok((releases, stats))
+}
+
+// Splits a paragraph into lines; text in links, spans and the like is taken as
+// is, so only <br>, <strong> and <em> matter
+fn walk(element: ElementRef, line: &mut Line, lines: &mut Vec<Line>) {
+    for child in element.children() {
+        match (child.value(), ElementRef::wrap(child)) {
+            // Entities are already decoded by the parser
+            (Node::Text(text), _) => line.push(text),
+            (_, Some(child)) => match child.value().name() {
+                "br" => lines.push(std::mem::take(line)),
+                "strong" | "b" if line.is_empty() => line.heading = Some(child.text().collect()),
+                "em" | "i" if line.album.is_none() => line.album = Some(child.text().collect()),
+                _ => walk(child, line, lines),
+            },
+            _ => {}
+        }
+    }
+}
+
+// Headings look like "January 6, 2023"; some months are abbreviated
+fn parse_date(heading: &str) -> Option<NaiveDate> {
+    let heading = squash(heading).replace('.', "");
+
+    ["%B %d, %Y", "%b %d, %Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(&heading, format).ok())
+}
+
+// Returns the artists, album and label of an entry; the label is optional
+fn parse_entry(line: &Line) -> Option<(Vec<String>, String, String)> {
+    let (artist, album, label) = match &line.album {
+        Some(album) => {
+            // Artists may contain " - " themselves, so only the separator right
+            // before the album is dropped
+            let artist = line.before.trim_end().trim_end_matches(['-', '–', '—']);
+
+            (squash(artist), squash(album), label(&line.after))
+        }
+        // Older entries have the album in plain text
+        None => {
+            let (rest, label) = match line.before.trim_end().strip_suffix(')') {
+                Some(rest) => match rest.rsplit_once('(') {
+                    Some((rest, label)) => (rest, squash(label)),
+                    None => (line.before.as_str(), String::new()),
+                },
+                None => (line.before.as_str(), String::new()),
+            };
+
+            let (artist, album) = SEPARATORS
+                .iter()
+                .find_map(|separator| rest.split_once(separator))?;
+
+            (squash(artist), squash(album), label)
+        }
+    };
+
+    if artist.is_empty() || album.is_empty() {
+        return None;
+    }
+
+    let artists = artist
+        .split(ARTIST_SEPARATOR)
+        .map(squash)
+        .filter(|a| !a.is_empty())
+        .collect();
+
+    Some((artists, album, label))
+}
+
+// The label is the first parenthesized part after the album; anything after it
+// (ie. "reissue") is dropped
+fn label(after: &str) -> String {
+    let after = after.trim();
+
+    match after.strip_prefix('(').and_then(|a| a.split_once(')')) {
+        Some((label, _)) => squash(label),
+        None => squash(after.trim_start_matches('(')),
+    }
+}
+
+// Collapses whitespace, including non-breaking spaces and newlines in the markup
+fn squash(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Fixtures are hand-written approximations of Loudwire's calendar markup,
+    // one quirk per file; a corpus of archived Loudwire pages is still to come
+    fn parse_fixture(html: &str) -> (Vec<(String, String, String, String)>, ScrapeStats) {
+        let (releases, stats) = parse(html).unwrap();
+
+        let releases = releases
+            .into_iter()
+            .map(|r| (r.date.to_string(), r.artist, r.album, r.label))
+            .collect();
+
+        (releases, stats)
+    }
+
+    fn entry(
+        date: &str,
+        artist: &str,
+        album: &str,
+        label: &str,
+    ) -> (String, String, String, String) {
+        (
+            date.to_string(),
+            artist.to_string(),
+            album.to_string(),
+            label.to_string(),
+        )
+    }
+
+    #[test]
+    fn parse_basic() {
+        let (releases, stats) = parse_fixture(include_str!("fixtures/basic.html"));
+
+        assert_eq!(
+            releases,
+            vec![
+                entry("2023-01-13", "Obituary", "Dying of Everything", "Relapse"),
+                entry("2023-01-20", "Katatonia", "Sky Void of Stars", "Napalm"),
+                entry("2023-01-20", "Riverside", "ID.Entity", "InsideOut"),
+            ]
+        );
+        assert_eq!(stats.paragraphs, 3);
+        assert_eq!(stats.days, 2);
+        assert_eq!(stats.failures, 0);
+    }
+
+    #[test]
+    fn parse_bold_artists() {
+        let (releases, stats) = parse_fixture(include_str!("fixtures/bold.html"));
+
+        assert_eq!(
+            releases,
+            vec![
+                entry("2023-01-20", "Katatonia", "Sky Void of Stars", "Napalm"),
+                entry("2023-01-20", "Riverside", "ID.Entity", "InsideOut"),
+            ]
+        );
+        assert_eq!(stats.days, 1);
+        assert_eq!(stats.failures, 0);
+    }
+
+    #[test]
+    fn parse_days_in_one_paragraph() {
+        let (releases, stats) = parse_fixture(include_str!("fixtures/days.html"));
+
+        // Releases under the misspelt heading aren't put on January 13
+        assert_eq!(
+            releases,
+            vec![
+                entry("2023-01-13", "Obituary", "Dying of Everything", "Relapse"),
+                entry(
+                    "2023-01-27",
+                    "Sorcerer",
+                    "Reign of the Reaper",
+                    "Metal Blade"
+                ),
+            ]
+        );
+        assert_eq!(stats.paragraphs, 1);
+        assert_eq!(stats.days, 3);
+        assert_eq!(stats.failures, 1);
+    }
+
+    #[test]
+    fn parse_entities() {
+        let (releases, _) = parse_fixture(include_str!("fixtures/entities.html"));
+
+        assert_eq!(
+            releases,
+            vec![
+                entry("2022-03-11", "Ghost", "Impera", "Loma Vista"),
+                entry(
+                    "2022-08-26",
+                    "Machine Head",
+                    "Øf Kingdøm and Crøwn",
+                    "Nuclear Blast"
+                ),
+                entry(
+                    "2022-10-07",
+                    "Queensrÿche",
+                    "Digital Noise Alliance",
+                    "Century Media"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_links() {
+        let (releases, _) = parse_fixture(include_str!("fixtures/links.html"));
+
+        assert_eq!(
+            releases,
+            vec![
+                entry("2023-03-03", "Enslaved", "Heimdal", "Nuclear Blast"),
+                entry("2023-04-14", "Metallica", "72 Seasons", "Blackened"),
+                entry("2023-04-14", "Overkill", "Scorched", "Nuclear Blast"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_splits() {
+        let (releases, _) = parse_fixture(include_str!("fixtures/splits.html"));
+
+        assert_eq!(
+            releases,
+            vec![
+                entry("2016-04-08", "Cult of Luna", "Mariner", "Indie"),
+                entry("2016-04-08", "Julie Christmas", "Mariner", "Indie"),
+                entry(
+                    "2023-12-08",
+                    "Full of Hell",
+                    "When No Birds Sang",
+                    "Closed Casket Activities"
+                ),
+                entry(
+                    "2023-12-08",
+                    "Nothing",
+                    "When No Birds Sang",
+                    "Closed Casket Activities"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_missing_labels() {
+        let (releases, stats) = parse_fixture(include_str!("fixtures/missing_labels.html"));
+
+        assert_eq!(
+            releases,
+            vec![
+                entry("2023-05-05", "Unearth", "The Wretched; The Ruinous", ""),
+                entry("2023-05-12", "Cattle Decapitation", "Terrasite", ""),
+                entry("2023-06-09", "Atreyu", "The Hope of a Spark", "Spinefarm"),
+                entry("2023-09-15", "Baroness", "Stone", ""),
+            ]
+        );
+        assert_eq!(stats.failures, 0);
+    }
+
+    #[test]
+    fn parse_dashes() {
+        let (releases, _) = parse_fixture(include_str!("fixtures/dashes.html"));
+
+        assert_eq!(
+            releases,
+            vec![
+                entry(
+                    "2016-01-20",
+                    "Jesu - Sun Kil Moon",
+                    "Jesu/Sun Kil Moon",
+                    "Caldo Verde"
+                ),
+                entry(
+                    "2023-05-12",
+                    "Cattle Decapitation",
+                    "Terrasite",
+                    "Metal Blade"
+                ),
+                entry("2023-09-15", "Baroness", "Stone", "Abraxan Hymns"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_broken() {
+        let (releases, stats) = parse_fixture(include_str!("fixtures/broken.html"));
+
+        assert_eq!(
+            releases,
+            vec![entry("2023-04-14", "Metallica", "72 Seasons", "Blackened")]
+        );
+        assert_eq!(stats.days, 2);
+        // An unknown date heading plus an entry without a separator
+        assert_eq!(stats.failures, 2);
+    }
+
+    #[test]
+    fn parse_layout_change() {
+        let (releases, stats) = parse_fixture(include_str!("fixtures/layout_change.html"));
+
+        assert!(releases.is_empty());
+        assert_eq!(stats.paragraphs, 0);
+    }
+}
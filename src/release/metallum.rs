@@ -1,10 +1,12 @@
 use crate::config::{MetallumArtistInfo, MetallumSearchResponse};
-use crate::AppError;
+use crate::{normalize, AppError};
 use log::{debug, error};
 use reqwest::Client;
 use scraper::{Html, Selector};
 
 const SEARCH_URL: &str = "https://www.metal-archives.com/search/ajax-band-search";
+// Band pages are found by ID alone, whatever the name in the URL
+const BAND_URL: &str = "https://www.metal-archives.com/bands/_";
 
 pub struct Metallum {
     pub client: Client,
@@ -24,20 +26,15 @@ impl Metallum {
     ) -> Result<Vec<MetallumArtistInfo>, AppError> {
         let mut artists: Vec<MetallumArtistInfo> = Vec::new();
 
-        let request = self
-            .client
-            .get(SEARCH_URL)
-            .query(&[("field", "name"), ("query", artist_name)]);
+        let candidates = self.search(artist_name).await?;
 
-        let response: MetallumSearchResponse = request.send().await?.json().await?;
-
-        if response.aa_data.is_empty() {
+        if candidates.is_empty() {
             debug!("No artists found in metallum for artist {}", artist_name);
             return Ok(artists); // This should probably be &
         }
 
         // At least one artist found - use it
-        for artist in response.aa_data {
+        for artist in candidates {
             // artist.0 == html with URL to artist
             // artist.1 == genre
             // artist.2 == country
@@ -63,7 +60,7 @@ impl Metallum {
                 artist_name, artist_url
             );
 
-            match self.get_artist_info(&artist_name, &artist_url).await {
+            match self.get_artist_info(artist_name, &artist_url).await {
                 Ok(artist_info) => {
                     artists.push(artist_info);
                     break;
@@ -83,6 +80,96 @@ impl Metallum {
         Ok(artists)
     }
 
+    // Like get_artists(), but only takes a band with the same name; for names
+    // that didn't come from the release calendar, where a partial match is
+    // likely a different band
+    pub async fn find_artist(
+        &self,
+        artist_name: &str,
+    ) -> Result<Option<MetallumArtistInfo>, AppError> {
+        let candidates = self.search(artist_name).await?;
+
+        for (html, _, _) in candidates
+            .iter()
+            .take_while(|(html, _, _)| same_name(html, artist_name))
+        {
+            let artist_url = match get_artist_url(html) {
+                Some(url) => url,
+                None => {
+                    error!("Could not determine artist URL in '{}'", html);
+                    continue;
+                }
+            };
+
+            return self
+                .get_artist_info(artist_name, &artist_url)
+                .await
+                .map(Some);
+        }
+
+        debug!("No band named '{}' found in metallum", artist_name);
+
+        Ok(None)
+    }
+
+    // Search results include partial matches; ones with the same name come first
+    async fn search(&self, artist_name: &str) -> Result<Vec<(String, String, String)>, AppError> {
+        let request = self
+            .client
+            .get(SEARCH_URL)
+            .query(&[("field", "name"), ("query", artist_name)]);
+
+        let response: MetallumSearchResponse = request.send().await?.json().await?;
+
+        let mut candidates = response.aa_data;
+        candidates.sort_by_key(|(html, _, _)| !same_name(html, artist_name));
+
+        Ok(candidates)
+    }
+
+    // The name on the band's page, ie. "Enslaved" for 159; None if there's no
+    // band with the ID
+    pub async fn band_name(&self, id: &str) -> Result<Option<String>, AppError> {
+        let resp = self
+            .client
+            .get(format!("{}/{}", BAND_URL, id))
+            .send()
+            .await?;
+
+        match resp.status() {
+            reqwest::StatusCode::OK => {}
+            reqwest::StatusCode::NOT_FOUND => return Ok(None),
+            status => {
+                return Err(AppError::GenericError(format!(
+                    "Received non-200 status code from metallum: {}",
+                    status
+                )))
+            }
+        }
+
+        let document = Html::parse_document(&resp.text().await?);
+
+        Ok(document
+            .select(&Selector::parse("h1.band_name")?)
+            .next()
+            .map(|name| name.text().collect::<String>().trim().to_string())
+            .filter(|name| !name.is_empty()))
+    }
+
+    // Metal Archives images can't be hotlinked, so fetch them with our own client
+    pub async fn download_image(&self, url: &str) -> Result<Vec<u8>, AppError> {
+        let resp = self.client.get(url).send().await?;
+
+        if resp.status() != reqwest::StatusCode::OK {
+            return Err(AppError::GenericError(format!(
+                "Received non-200 status code from metallum: {}",
+                resp.status()
+            )));
+        }
+
+        Ok(resp.bytes().await?.to_vec())
+    }
+
     async fn get_artist_info(
         &self,
         artist_name: &str,
@@ -93,12 +180,10 @@ impl Metallum {
         let resp = reqwest::get(artist_url).await?;
 
         if resp.status() != reqwest::StatusCode::OK {
-            return Err(AppError::GenericError {
-                0: format!(
-                    "Received non-200 status code from metallum: {}",
-                    resp.status()
-                ),
-            });
+            return Err(AppError::GenericError(format!(
+                "Received non-200 status code from metallum: {}",
+                resp.status()
+            )));
         }
 
         let body = resp.text().await?;
@@ -107,7 +192,7 @@ impl Metallum {
 
         let document = Html::parse_document(&body);
 
-        Ok(self.parse_band_info(&artist_name, &artist_url, &document)?)
+        self.parse_band_info(artist_name, artist_url, &document)
     }
 
     pub fn parse_band_info(
@@ -122,9 +207,9 @@ impl Metallum {
         let entry = document
             .select(&band_info_selector)
             .next()
-            .ok_or(AppError::GenericError {
-                0: "Could not find band info".to_string(),
-            })?;
+            .ok_or(AppError::GenericError(
+                "Could not find band info".to_string(),
+            ))?;
 
         let vector = entry
             .select(&Selector::parse("dl > dd")?)
@@ -132,12 +217,10 @@ impl Metallum {
             .collect::<Vec<_>>();
 
         if vector.len() != 8 {
-            return Err(AppError::GenericError {
-                0: format!(
-                    "Unexpected number of elements in band_info (expected 8, got {})",
-                    vector.len()
-                ),
-            });
+            return Err(AppError::GenericError(format!(
+                "Unexpected number of elements in band_info (expected 8, got {})",
+                vector.len()
+            )));
         }
 
         let country_origin = &vector[0];
@@ -150,33 +233,31 @@ impl Metallum {
         let years_active = &vector[7];
 
         // Origin is a link
-        let country_origin = parse_link(&country_origin, "N/A")?;
+        let country_origin = parse_link(country_origin, "N/A")?;
 
         // Label is a link as well
-        let last_label = parse_link(&last_label, "N/A")?;
+        let last_label = parse_link(last_label, "N/A")?;
 
         // Get band description
         let band_bio_selector = Selector::parse("div.band_comment")?;
 
-        let fragment =
-            document
-                .select(&band_bio_selector)
-                .next()
-                .ok_or(AppError::GenericError {
-                    0: "Could not find band description".to_string(),
-                })?;
+        let fragment = document
+            .select(&band_bio_selector)
+            .next()
+            .ok_or(AppError::GenericError(
+                "Could not find band description".to_string(),
+            ))?;
 
         let description_short = voca_rs::strip::strip_tags(&fragment.inner_html());
 
         // Get img URL
         let band_img_selector = Selector::parse("#band_sidebar > div.band_img > a")?;
-        let fragment =
-            document
-                .select(&band_img_selector)
-                .next()
-                .ok_or(AppError::GenericError {
-                    0: "Could not find band img".to_string(),
-                })?;
+        let fragment = document
+            .select(&band_img_selector)
+            .next()
+            .ok_or(AppError::GenericError(
+                "Could not find band img".to_string(),
+            ))?;
 
         let mut band_img_url = "".to_string();
 
@@ -190,9 +271,9 @@ impl Metallum {
             document
                 .select(&band_name_img_selector)
                 .next()
-                .ok_or(AppError::GenericError {
-                    0: "Could not find band name img".to_string(),
-                })?;
+                .ok_or(AppError::GenericError(
+                    "Could not find band name img".to_string(),
+                ))?;
 
         let mut band_name_img_url = "".to_string();
 
@@ -221,6 +302,12 @@ impl Metallum {
     }
 }
 
+// Whether a search result links to a band named `artist_name`
+fn same_name(html: &str, artist_name: &str) -> bool {
+    let name = parse_link(html, "").unwrap_or_default();
+    normalize::same_artist(&name, artist_name)
+}
+
 fn get_artist_url(html: &str) -> Option<String> {
     let end = html.find("\">")?;
 
@@ -229,7 +316,7 @@ fn get_artist_url(html: &str) -> Option<String> {
 }
 
 fn parse_link(input: &str, default: &str) -> Result<String, AppError> {
-    match Html::parse_fragment(&input)
+    match Html::parse_fragment(input)
         .select(&Selector::parse("a")?)
         .next()
     {
@@ -1,128 +1,145 @@
+mod loudwire;
 mod metallum;
 mod spotify;
 
-use crate::config::{Config, Release, SpotifyArtistInfo};
+use crate::config::{
+    Config, DateChange, MetallumArtistInfo, Release, ReleaseStatus, SpotifyArtistInfo, Triage,
+};
+use crate::notifier::Notice;
 use crate::release::spotify::Spotify;
-use crate::AppError;
-use chrono::prelude::{Local, NaiveDate, Utc};
-use log::{debug, info};
+use crate::{feedback, metrics, normalize, AppError};
+use chrono::prelude::{Datelike, Local, NaiveDate, Utc};
+use chrono::Duration;
+use log::{debug, info, warn};
 use metallum::Metallum;
-use regex::Regex;
-use scraper::{Html, Selector};
+use std::collections::{HashMap, HashSet};
+
+const SOURCE: &str = "loudwire";
 
 const LOUDWIRE_URL: &str = "https://loudwire.com/2023-hard-rock-metal-album-release-calendar/";
 
+const ENTRY_SELECTOR: &str = "div.pod-content > p";
+
+// What a fetch ran into; used to tell a layout change apart from a quiet week
+#[derive(Debug, Default)]
+pub struct ScrapeStats {
+    // Paragraphs matched by ENTRY_SELECTOR
+    pub paragraphs: usize,
+    // Date headings found (bold lines that aren't entries), including ones
+    // that couldn't be parsed
+    pub days: usize,
+    // Date headings that couldn't be parsed plus entries that couldn't; the
+    // releases under such a heading are dropped, not counted
+    pub failures: usize,
+}
+
 // Fetches latest releases from release
-pub async fn fetch_releases() -> Result<Vec<Release>, AppError> {
+pub async fn fetch_releases() -> Result<(Vec<Release>, ScrapeStats), AppError> {
     let resp = reqwest::get(LOUDWIRE_URL).await?;
 
     if resp.status() != reqwest::StatusCode::OK {
-        return Err(AppError::GenericError {
-            0: format!("Received non-200 status code: {}", resp.status()),
-        });
+        return Err(AppError::GenericError(format!(
+            "Received non-200 status code: {}",
+            resp.status()
+        )));
     }
 
     let body = resp.text().await?;
 
-    // Parse the document
-    let fragment = Html::parse_document(&body);
+    let (releases, stats) = loudwire::parse(&body)?;
 
-    let closer = Selector::parse("div.pod-content > p")?;
-
-    let mut releases = Vec::new();
-
-    for entry in fragment.select(&closer) {
-        if entry.html().starts_with("<p><strong>") {
-            match parse_releases(entry.html()) {
-                Ok(partial_releases) => {
-                    for release in partial_releases {
-                        releases.push(release);
-                    }
-                }
-                Err(e) => {
-                    // Only explode on issues unrelated to date parsing
-                    if e.to_string().contains("Could not parse date") {
-                        continue;
-                    } else {
-                        return Err(AppError::GenericError {
-                            0: format!("Could not parse date: {}", e.to_string()),
-                        });
-                    }
-                }
-            };
-        }
-    }
+    metrics::add(
+        metrics::RELEASES_FETCHED,
+        &[("source", SOURCE)],
+        releases.len() as u64,
+    );
 
-    Ok(releases)
+    Ok((releases, stats))
 }
 
-fn parse_releases(html: String) -> Result<Vec<Release>, AppError> {
-    let mut releases = Vec::new();
-
-    // Parse date
-    let date_re = Regex::new(r"^<p><strong>(\w+ \d{1,2}, \d{4})</strong>")?;
+// Weeks (from the current one) that have to have releases in them for a fetch
+// to count as current; a single week can be quiet, ie. around the holidays
+const SCRAPE_WEEKS: i64 = 4;
 
-    let date = match date_re.captures(&html) {
-        Some(caps) => {
-            let date_str = caps.get(1).map_or("", |m| m.as_str());
-            NaiveDate::parse_from_str(date_str, "%B %d, %Y")?
-        }
-        None => {
-            return Err(AppError::GenericError {
-                0: format!("Could not parse date from {}", html),
-            })
-        }
-    };
-
-    // Parse releases
-    let split_releases = html.split("<br>");
-
-    for s in split_releases {
-        // If regex doesn't match, move on to next entry
-        let re = Regex::new(r"^(.+) - <em>(.+)</em>(?:\s+)?\(?(.+)\)(?:</p>)?$")?;
-        let caps = match re.captures(s) {
-            Some(caps) => caps,
-            None => {
-                // Regex didn't match
-                continue;
-            }
-        };
+// Sanity checks on a fetch; a layout change otherwise just looks like there
+// being no releases today
+pub fn check_scrape(
+    config: &Config,
+    releases: &[Release],
+    stats: &ScrapeStats,
+) -> Result<(), AppError> {
+    check_scrape_on(config, releases, stats, Local::now().date_naive())
+}
 
-        // Not sure if this is even possible
-        if caps.len() != 4 {
-            continue;
-        }
+fn check_scrape_on(
+    config: &Config,
+    releases: &[Release],
+    stats: &ScrapeStats,
+    today: NaiveDate,
+) -> Result<(), AppError> {
+    if stats.paragraphs == 0 {
+        return Err(AppError::ScraperError(format!(
+            "'{}' matched nothing on {}",
+            ENTRY_SELECTOR, LOUDWIRE_URL
+        )));
+    }
 
-        let artist = caps.get(1).map_or("", |m| m.as_str());
-        let album = caps.get(2).map_or("", |m| m.as_str());
-        let label = caps.get(3).map_or("", |m| m.as_str());
+    if stats.days == 0 {
+        return Err(AppError::ScraperError(format!(
+            "No date headings found in {} paragraphs on {}",
+            stats.paragraphs, LOUDWIRE_URL
+        )));
+    }
 
-        // TODO: Try out the default trait
-        let release = Release {
-            date,
-            artist: String::from(artist),
-            album: String::from(album),
-            label: label.replace("(", ""),
-            spotify: None,
-            metallum: None,
-            skip: false,
-            skip_reasons: vec![],
-        };
+    let attempted = releases.len() + stats.failures;
+    let ratio = stats.failures as f64 / attempted as f64;
+
+    if attempted > 0 && ratio > config.scraper_max_failure_ratio {
+        return Err(AppError::ScraperError(format!(
+            "{} of {} entries could not be parsed ({:.0}%, at most {:.0}% allowed)",
+            stats.failures,
+            attempted,
+            ratio * 100.0,
+            config.scraper_max_failure_ratio * 100.0
+        )));
+    }
 
-        releases.push(release);
+    // Nearly every week has releases; none for weeks means the page stopped
+    // being updated
+    let week = today.iso_week();
+    let week_start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let weeks_end = week_start + Duration::weeks(SCRAPE_WEEKS);
+
+    if !releases
+        .iter()
+        .any(|r| r.date >= week_start && r.date < weeks_end)
+    {
+        return Err(AppError::ScraperError(format!(
+            "No releases for the {} weeks from week {} of {} among {} fetched",
+            SCRAPE_WEEKS,
+            week.week(),
+            week.year(),
+            releases.len()
+        )));
     }
 
-    releases.sort_by_key(|r| r.date);
+    if !releases.iter().any(|r| r.date.iso_week() == week) {
+        warn!(
+            "No releases for week {} of {}; a quiet week, or the page isn't being updated",
+            week.week(),
+            week.year()
+        );
+    }
 
-    Ok(releases)
+    Ok(())
 }
 
 pub fn out_of_date(config: &Config) -> bool {
     let now = Utc::now();
     let last_update = config.last_update;
 
-    // If last update was more than 24 hours ago, return true
-    now.signed_duration_since(last_update).num_hours() > 24
+    // If last update was longer ago than the fetch interval, return true
+    now.signed_duration_since(last_update).num_hours() >= config.fetch_interval_hours as i64
 }
 
 // Q: Read somewhere that it's better to accept a slice than a vector? Should I do that?
@@ -131,7 +148,10 @@ pub fn get_releases_today(releases: &Vec<Release>) -> Vec<Release> {
     let mut releases_today: Vec<Release> = Vec::new();
 
     for release in releases {
-        if release.date == Local::now().date_naive() {
+        // Cancelled releases stay in the store in case they come back
+        if release.date == Local::now().date_naive()
+            && release.status != Some(ReleaseStatus::Cancelled)
+        {
             // Q: I am creating a copy here; how can I return a slice of refs to existing releases?
             releases_today.push(release.clone());
         }
@@ -146,7 +166,16 @@ pub async fn enrich_with_spotify(
     client_secret: String,
     releases: &mut Vec<Release>,
 ) -> Result<(), AppError> {
-    let spotify_client = Spotify::new(client_id.as_str(), client_secret.as_str()).await?;
+    let spotify_client = match Spotify::new(client_id.as_str(), client_secret.as_str()).await {
+        Ok(client) => client,
+        Err(e) => {
+            metrics::inc(
+                metrics::ENRICHMENT,
+                &[("source", "spotify"), ("result", "error")],
+            );
+            return Err(e);
+        }
+    };
 
     for release in releases {
         // Skip entries that have already been processed/reviewed/etc.
@@ -160,7 +189,7 @@ pub async fn enrich_with_spotify(
         }
 
         // Skip if there is already spotify data for artist/release
-        if !release.spotify.is_none() {
+        if release.spotify.is_some() {
             debug!(
                 "Skipping artist lookup for artist '{}' - already exists",
                 release.artist
@@ -170,14 +199,32 @@ pub async fn enrich_with_spotify(
         }
 
         // Fetch release.spotify data here
-        let spotify_artist_info = spotify_client.get_artists(release.artist.as_str()).await?;
+        let spotify_artist_info = match spotify_client.get_artists(release.artist.as_str()).await {
+            Ok(info) => info,
+            Err(e) => {
+                metrics::inc(
+                    metrics::ENRICHMENT,
+                    &[("source", "spotify"), ("result", "error")],
+                );
+                return Err(e);
+            }
+        };
 
-        if spotify_artist_info.len() == 0 {
+        if spotify_artist_info.is_empty() {
+            metrics::inc(
+                metrics::ENRICHMENT,
+                &[("source", "spotify"), ("result", "miss")],
+            );
             continue;
         }
 
+        metrics::inc(
+            metrics::ENRICHMENT,
+            &[("source", "spotify"), ("result", "hit")],
+        );
+
         // Always grab only the top-level artist
-        if spotify_artist_info.len() >= 1 {
+        if !spotify_artist_info.is_empty() {
             release.spotify = Some(SpotifyArtistInfo {
                 id: spotify_artist_info[0].id.to_string(),
                 url: spotify_artist_info[0].href.clone(),
@@ -193,6 +240,81 @@ pub async fn enrich_with_spotify(
     Ok(())
 }
 
+pub async fn download_metallum_image(url: &str) -> Result<Vec<u8>, AppError> {
+    Metallum::new()?.download_image(url).await
+}
+
+// Looks up bands by exact name, one at a time with the same client
+pub async fn find_metallum_artists(
+    names: &[String],
+) -> Result<Vec<Option<MetallumArtistInfo>>, AppError> {
+    let metallum = Metallum::new()?;
+    let mut artists = vec![];
+
+    for name in names {
+        artists.push(metallum.find_artist(name).await?);
+    }
+
+    Ok(artists)
+}
+
+// Names follows given only by a Spotify or Metal Archives ID, so their releases
+// are recognized (and announced) when they show up on the calendar rather than
+// once they're enriched on the day. Lookups that fail are tried again next time.
+pub async fn name_follows(config: &mut Config) {
+    let mut spotify = None;
+    let mut metallum = None;
+
+    for follow in config.follows.iter_mut().filter(|f| f.name.is_none()) {
+        let name = match (&follow.spotify_id, &follow.metallum_id) {
+            (Some(id), _) => {
+                if spotify.is_none() {
+                    spotify = Some(
+                        Spotify::new(&config.spotify_client_id, &config.spotify_client_secret)
+                            .await,
+                    );
+                }
+
+                match spotify.as_ref() {
+                    Some(Ok(spotify)) => spotify.artist_name(id).await,
+                    _ => continue,
+                }
+            }
+            (None, Some(id)) => {
+                if metallum.is_none() {
+                    metallum = Some(Metallum::new());
+                }
+
+                match metallum.as_ref() {
+                    Some(Ok(metallum)) => metallum.band_name(id).await,
+                    _ => continue,
+                }
+            }
+            (None, None) => continue,
+        };
+
+        match name {
+            Ok(Some(name)) => {
+                info!("Following '{}' by name", name);
+                follow.name = Some(name);
+            }
+            Ok(None) => warn!("No artist found for follow {:?}", follow),
+            Err(e) => warn!("Could not look up the name of follow {:?}: {}", follow, e),
+        }
+    }
+
+    if let Some(Err(e)) = spotify {
+        warn!("Could not look up followed artists on Spotify: {}", e);
+    }
+
+    if let Some(Err(e)) = metallum {
+        warn!(
+            "Could not look up followed artists on Metal Archives: {}",
+            e
+        );
+    }
+}
+
 pub async fn enrich_with_metallum(releases: &mut Vec<Release>) -> Result<(), AppError> {
     let metallum = Metallum::new()?;
 
@@ -221,9 +343,23 @@ pub async fn enrich_with_metallum(releases: &mut Vec<Release>) -> Result<(), App
 
         debug!("Looking up metallum info for artist '{}'", release.artist);
 
-        let metallum_artists = metallum.get_artists(release.artist.as_str()).await?;
+        let metallum_artists = match metallum.get_artists(release.artist.as_str()).await {
+            Ok(artists) => artists,
+            Err(e) => {
+                metrics::inc(
+                    metrics::ENRICHMENT,
+                    &[("source", "metallum"), ("result", "error")],
+                );
+                return Err(e);
+            }
+        };
 
-        if metallum_artists.len() == 0 {
+        if metallum_artists.is_empty() {
+            metrics::inc(
+                metrics::ENRICHMENT,
+                &[("source", "metallum"), ("result", "miss")],
+            );
+            metrics::inc(metrics::SKIPPED, &[("reason", "no_metallum_data")]);
             release.skip = true;
             release
                 .skip_reasons
@@ -232,7 +368,12 @@ pub async fn enrich_with_metallum(releases: &mut Vec<Release>) -> Result<(), App
             continue;
         }
 
-        if metallum_artists.len() >= 1 {
+        metrics::inc(
+            metrics::ENRICHMENT,
+            &[("source", "metallum"), ("result", "hit")],
+        );
+
+        if !metallum_artists.is_empty() {
             // Use the first hit for now - good enough
             release.metallum = Some(metallum_artists[0].clone());
 
@@ -243,10 +384,11 @@ pub async fn enrich_with_metallum(releases: &mut Vec<Release>) -> Result<(), App
     Ok(())
 }
 
-pub fn set_skip_spotify(config: &Config, releases_today: &mut Vec<Release>) {
+pub fn set_skip_spotify(config: &Config, releases_today: &mut [Release]) {
     'main: for release in releases_today.iter_mut() {
-        // No need to review/set skip if already set as skipped
-        if release.skip {
+        // No need to review/set skip if already set as skipped; followed
+        // releases are never skipped
+        if release.skip || release.followed {
             continue;
         }
 
@@ -263,6 +405,7 @@ pub fn set_skip_spotify(config: &Config, releases_today: &mut Vec<Release>) {
                 release.spotify.is_none()
             );
 
+            metrics::inc(metrics::SKIPPED, &[("reason", "no_spotify_data")]);
             release.skip = true;
             release
                 .skip_reasons
@@ -275,6 +418,7 @@ pub fn set_skip_spotify(config: &Config, releases_today: &mut Vec<Release>) {
 
         // Skip if followers too low
         if spotify_metadata.followers < 1000 {
+            metrics::inc(metrics::SKIPPED, &[("reason", "low_followers")]);
             release.skip = true;
             release
                 .skip_reasons
@@ -285,6 +429,7 @@ pub fn set_skip_spotify(config: &Config, releases_today: &mut Vec<Release>) {
 
         // Skip if there is no genre specification
         if spotify_metadata.genres.is_empty() {
+            metrics::inc(metrics::SKIPPED, &[("reason", "no_spotify_genres")]);
             release.skip = true;
             release
                 .skip_reasons
@@ -307,6 +452,7 @@ pub fn set_skip_spotify(config: &Config, releases_today: &mut Vec<Release>) {
                         release.artist, genre, blacklisted_genre
                     );
 
+                    metrics::inc(metrics::SKIPPED, &[("reason", "blacklisted_genre")]);
                     release.skip = true;
                     release.skip_reasons.push(format!(
                         "blacklisted genre keyword '{}' found in genre '{:?}'",
@@ -332,10 +478,11 @@ pub fn set_skip_spotify(config: &Config, releases_today: &mut Vec<Release>) {
     }
 }
 
-pub fn set_skip_metallum(config: &Config, releases_today: &mut Vec<Release>) {
+pub fn set_skip_metallum(config: &Config, releases_today: &mut [Release]) {
     for release in releases_today.iter_mut() {
-        // No need to review/set skip if already set as skipped
-        if release.skip {
+        // No need to review/set skip if already set as skipped; followed
+        // releases are never skipped
+        if release.skip || release.followed {
             continue;
         }
 
@@ -344,11 +491,10 @@ pub fn set_skip_metallum(config: &Config, releases_today: &mut Vec<Release>) {
             continue;
         }
 
-        let metallum_metadata;
-
-        match release.metallum {
-            Some(ref m) => metallum_metadata = m,
+        let metallum_metadata = match release.metallum {
+            Some(ref m) => m,
             None => {
+                metrics::inc(metrics::SKIPPED, &[("reason", "no_metallum_data")]);
                 release.skip = true;
                 release
                     .skip_reasons
@@ -356,10 +502,11 @@ pub fn set_skip_metallum(config: &Config, releases_today: &mut Vec<Release>) {
 
                 continue;
             }
-        }
+        };
 
         // Metallum exists; skip if genres is empty though
         if metallum_metadata.genre.is_empty() {
+            metrics::inc(metrics::SKIPPED, &[("reason", "no_metallum_genres")]);
             release.skip = true;
             release
                 .skip_reasons
@@ -383,7 +530,10 @@ pub fn set_skip_metallum(config: &Config, releases_today: &mut Vec<Release>) {
             }
         }
 
-        // Blacklist check
+        // Blacklist check; every matching keyword is a reason, but the release
+        // is counted once
+        let mut blacklisted = false;
+
         for b_keyword in &config.blacklisted_genre_keywords {
             if metallum_metadata
                 .genre
@@ -394,48 +544,571 @@ pub fn set_skip_metallum(config: &Config, releases_today: &mut Vec<Release>) {
                     "Band '{}' has blacklisted genre keyword '{}' - skipping!",
                     release.artist, b_keyword
                 );
+                blacklisted = true;
                 release.skip = true;
                 release.skip_reasons.push(format!(
                     "blacklisted genre keyword '{}' found in metallum genre '{:?}'",
                     b_keyword, metallum_metadata.genre
                 ));
+            }
+        }
+
+        if blacklisted {
+            metrics::inc(metrics::SKIPPED, &[("reason", "blacklisted_genre")]);
+        }
+    }
+}
+
+pub fn set_skip_feedback(config: &Config, releases_today: &mut [Release]) {
+    if config.affinity.is_empty() {
+        return;
+    }
+
+    for release in releases_today.iter_mut() {
+        if release.skip || release.followed {
+            continue;
+        }
+
+        let affinity = feedback::affinity(config, release);
+
+        if affinity <= config.feedback_skip_threshold {
+            debug!(
+                "Band '{}' has affinity {} from feedback - skipping!",
+                release.artist, affinity
+            );
+            metrics::inc(metrics::SKIPPED, &[("reason", "negative_feedback")]);
+            release.skip = true;
+            release
+                .skip_reasons
+                .push(format!("negative feedback (affinity {})", affinity));
+        }
+    }
+}
 
+// Upserts a fresh fetch into the release store by ID: stored releases keep their
+// enrichment, skip state and triage, and take the label and date from the fetch.
+// Stored releases missing from the fetch are kept if there's anything to keep;
+// upcoming ones are flagged as cancelled. Date changes and cancellations of
+// releases that would be posted are returned as notices.
+pub fn merge_fetched(stored: Vec<Release>, fetched: Vec<Release>) -> (Vec<Release>, Vec<Notice>) {
+    let mut stored: HashMap<String, Release> = stored.into_iter().map(|r| (id(&r), r)).collect();
+
+    let now = Utc::now();
+    let today = Local::now().date_naive();
+
+    let mut releases: Vec<Release> = vec![];
+    let mut notices = vec![];
+    let mut seen = HashSet::new();
+
+    for f in fetched {
+        let release_id = id(&f);
+
+        // The same release may be listed twice
+        if !seen.insert(release_id.clone()) {
+            continue;
+        }
+
+        let mut s = match stored.remove(&release_id) {
+            Some(s) => s,
+            None => {
+                releases.push(f);
                 continue;
             }
+        };
+
+        if s.status == Some(ReleaseStatus::Cancelled) {
+            info!(
+                "Release '{} - {}' is back on the calendar",
+                s.artist, s.album
+            );
+            s.status = None;
+        }
+
+        if s.date != f.date {
+            info!(
+                "Release '{} - {}' moved from {} to {}",
+                s.artist, s.album, s.date, f.date
+            );
+            metrics::inc(metrics::DATE_CHANGES, &[("source", SOURCE)]);
+
+            s.date_history.push(DateChange {
+                from: s.date,
+                to: f.date,
+                seen: now,
+            });
+            s.date = f.date;
+            // It's news on its new date
+            s.notified = false;
+
+            notices.extend(noticeable(&s).then(|| Notice {
+                text: format!(
+                    "{} - {} moved from {} to {}",
+                    s.artist,
+                    s.album,
+                    s.date_history[s.date_history.len() - 1]
+                        .from
+                        .format("%b %-d"),
+                    s.date.format("%b %-d")
+                ),
+                release: s.clone(),
+            }));
+        }
+
+        // A release moved to a later date stays postponed until it moves back
+        if s.status != Some(ReleaseStatus::Cancelled) {
+            let original = s.date_history.first().map_or(s.date, |c| c.from);
+
+            s.status = (s.date > original).then_some(ReleaseStatus::Postponed);
+        }
+
+        if !f.label.is_empty() {
+            s.label = f.label;
+        }
+
+        releases.push(s);
+    }
+
+    for mut s in stored.into_values() {
+        let upcoming = s.date >= today;
+
+        if upcoming && s.status != Some(ReleaseStatus::Cancelled) {
+            info!(
+                "Release '{} - {}' due {} is no longer on the calendar",
+                s.artist, s.album, s.date
+            );
+
+            s.status = Some(ReleaseStatus::Cancelled);
+            notices.extend(noticeable(&s).then(|| Notice {
+                text: format!(
+                    "{} - {} (due {}) is no longer on the release calendar",
+                    s.artist,
+                    s.album,
+                    s.date.format("%b %-d")
+                ),
+                release: s.clone(),
+            }));
+        }
+
+        if upcoming || s.spotify.is_some() || s.metallum.is_some() || s.skip || s.triage.is_some() {
+            releases.push(s);
         }
     }
+
+    releases.sort_by_cached_key(|r| (r.date, id(r)));
+
+    (releases, notices)
+}
+
+// Releases by followed artists, and ones that were enriched (or marked) and not
+// skipped; news about a release that was never going to be posted is noise
+fn noticeable(release: &Release) -> bool {
+    let enriched = release.spotify.is_some() || release.metallum.is_some();
+    let marked = matches!(release.triage, Some(Triage::Want | Triage::Listened));
+
+    release.followed
+        || (!release.skip && release.triage != Some(Triage::Skip) && (enriched || marked))
 }
 
+// Upserts enriched releases into the release store by ID; enrichment that
+// failed this time doesn't overwrite earlier enrichment
 pub fn merge_releases(all_releases: &mut Vec<Release>, todays_releases: &Vec<Release>) {
+    let index: HashMap<String, usize> = all_releases
+        .iter()
+        .enumerate()
+        .map(|(i, r)| (id(r), i))
+        .collect();
+
     for tr in todays_releases {
-        for ar in &mut *all_releases {
-            if tr.artist == ar.artist && tr.album == ar.album {
-                ar.spotify = tr.spotify.clone();
-                ar.metallum = tr.metallum.clone();
-                ar.skip = tr.skip;
-                ar.skip_reasons = tr.skip_reasons.clone();
+        let ar = match index.get(&id(tr)) {
+            Some(&i) => &mut all_releases[i],
+            None => {
+                all_releases.push(Release {
+                    updated: Some(Utc::now()),
+                    looked_up: true,
+                    ..tr.clone()
+                });
+                continue;
             }
+        };
+
+        let spotify = tr.spotify.clone().or_else(|| ar.spotify.clone());
+        let metallum = tr.metallum.clone().or_else(|| ar.metallum.clone());
+
+        if spotify != ar.spotify || metallum != ar.metallum {
+            ar.updated = Some(Utc::now());
         }
+
+        ar.spotify = spotify;
+        ar.metallum = metallum;
+        ar.skip = tr.skip;
+        ar.skip_reasons = tr.skip_reasons.clone();
+        ar.looked_up = true;
     }
 }
 
-pub fn filter_valid_releases(releases: &Vec<Release>) -> Vec<&Release> {
-    // Ignore skipped releases
+// Identifies a release across fetches and sources, ie. "metallica-72_seasons".
+// It's made of the normalized artist and album but not the date, so a postponed
+// release keeps its ID; calendar apps, feed readers and API clients rely on it.
+pub fn id(release: &Release) -> String {
+    id_of(&release.artist, &release.album)
+}
+
+// Whether two listings are the same release, ie. have the same ID
+pub fn same(a: &Release, b: &Release) -> bool {
+    normalize::same_artist(&a.artist, &b.artist) && normalize::same_album(&a.album, &b.album)
+}
+
+pub fn id_of(artist: &str, album: &str) -> String {
+    format!(
+        "{}-{}",
+        normalize::artist(artist).replace(' ', "_"),
+        normalize::album(album).replace(' ', "_")
+    )
+}
+
+// Enriched, non-skipped releases from the release store, oldest first
+pub fn stored_valid_releases(config: &Config) -> Vec<&Release> {
+    let mut releases: Vec<&Release> = config
+        .releases
+        .iter()
+        .filter(|r| !r.skip && r.triage != Some(Triage::Skip))
+        .filter(|r| r.status != Some(ReleaseStatus::Cancelled))
+        .filter(|r| r.spotify.is_some() && r.metallum.is_some())
+        .collect();
+
+    releases.sort_by_key(|r| r.date);
+
+    releases
+}
+
+pub fn filter_valid_releases<'a>(config: &Config, releases: &'a [Release]) -> Vec<&'a Release> {
+    // Ignore skipped releases, including ones skipped by hand
     let valid_releases = releases
-        .into_iter()
-        .filter(|r| !r.skip)
+        .iter()
+        .filter(|r| !r.skip && r.triage != Some(Triage::Skip))
         .collect::<Vec<&Release>>();
 
     let mut sorted_releases = valid_releases.clone();
 
-    // Sort desc by follower count
+    // Followed artists first, then sort desc by affinity from feedback, then by
+    // follower count; followed releases may not have been enriched
+    let followers = |r: &Release| r.spotify.as_ref().map_or(0, |s| s.followers);
+
     sorted_releases.sort_by(|a, b| {
-        b.spotify
-            .clone()
-            .unwrap()
-            .followers
-            .cmp(&a.spotify.clone().unwrap().followers)
+        b.followed
+            .cmp(&a.followed)
+            .then(feedback::affinity(config, b).cmp(&feedback::affinity(config, a)))
+            .then(followers(b).cmp(&followers(a)))
     });
 
     sorted_releases
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn release(date: &str, artist: &str, album: &str, label: &str) -> Release {
+        Release {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            artist: artist.to_string(),
+            album: album.to_string(),
+            label: label.to_string(),
+            skip: false,
+            skip_reasons: vec![],
+            spotify: None,
+            metallum: None,
+            updated: None,
+            triage: None,
+            status: None,
+            date_history: vec![],
+            followed: false,
+            announced: false,
+            looked_up: false,
+            notified: false,
+        }
+    }
+
+    fn spotify() -> Option<SpotifyArtistInfo> {
+        Some(SpotifyArtistInfo {
+            id: "2ye2Wgw4gimLv2eAKyk1NB".to_string(),
+            url: "https://open.spotify.com/artist/2ye2Wgw4gimLv2eAKyk1NB".to_string(),
+            genres: vec!["thrash metal".to_string()],
+            popularity: 80,
+            followers: 1000,
+        })
+    }
+
+    #[test]
+    fn scrape_checks() {
+        let config = Config {
+            scraper_max_failure_ratio: 0.25,
+            ..Default::default()
+        };
+        let stats = |paragraphs, days, failures| ScrapeStats {
+            paragraphs,
+            days,
+            failures,
+        };
+        // A Wednesday
+        let today = NaiveDate::from_ymd_opt(2023, 12, 27).unwrap();
+        let releases = vec![release("2023-12-25", "Enslaved", "Heimdal", "")];
+
+        let check = |releases: &[Release], stats: &ScrapeStats| {
+            check_scrape_on(&config, releases, stats, today)
+        };
+
+        assert!(check(&releases, &stats(10, 2, 0)).is_ok());
+
+        // The layout changed
+        assert!(check(&[], &stats(0, 0, 0)).is_err());
+        assert!(check(&[], &stats(10, 0, 0)).is_err());
+
+        // Too many entries couldn't be parsed
+        assert!(check(&releases, &stats(10, 2, 1)).is_err());
+        assert!(check(&vec![releases[0].clone(); 3], &stats(10, 2, 1)).is_ok());
+
+        // A quiet holiday week is fine as long as later weeks have releases
+        let later = vec![release("2024-01-12", "Enslaved", "Heimdal", "")];
+        assert!(check(&later, &stats(10, 2, 0)).is_ok());
+
+        // Nothing this week or the ones after it
+        let stale = vec![
+            release("2023-12-22", "Enslaved", "Heimdal", ""),
+            release("2024-01-22", "Opeth", "The Last Will and Testament", ""),
+        ];
+        assert!(check(&stale, &stats(10, 2, 0)).is_err());
+
+        // Every date heading stopped parsing
+        let (broken, broken_stats) = loudwire::parse(
+            "<div class=\"pod-content\">\
+             <p><strong>3/3/2023</strong><br>Enslaved - <em>Heimdal</em> (Nuclear Blast)</p>\
+             <p><strong>4/14/2023</strong><br>Metallica - <em>72 Seasons</em> (Blackened)</p>\
+             </div>",
+        )
+        .unwrap();
+
+        assert!(broken.is_empty());
+        assert_eq!((broken_stats.days, broken_stats.failures), (2, 2));
+        assert!(check(&broken, &broken_stats).is_err());
+    }
+
+    #[test]
+    fn blacklisted_once() {
+        let count = || {
+            metrics::render()
+                .lines()
+                .find_map(|l| {
+                    l.strip_prefix("metalpal_skipped_total{reason=\"blacklisted_genre\"} ")
+                })
+                .map_or(0, |n| n.parse::<u64>().unwrap())
+        };
+
+        let config = Config {
+            blacklisted_genre_keywords: vec!["black".to_string(), "death".to_string()],
+            ..Default::default()
+        };
+
+        let today = Local::now().date_naive().to_string();
+        let mut r = release(&today, "Behemoth", "The Shit ov God", "");
+        r.metallum = Some(MetallumArtistInfo {
+            genre: "Black/Death Metal".to_string(),
+            ..Default::default()
+        });
+
+        let before = count();
+        set_skip_metallum(&config, std::slice::from_mut(&mut r));
+
+        assert!(r.skip);
+        assert_eq!(r.skip_reasons.len(), 2);
+        assert_eq!(count(), before + 1);
+    }
+
+    #[test]
+    fn id_ignores_date_and_spelling() {
+        let a = release("2023-04-14", "Mötley Crüe", "Dr. Feelgood (Deluxe)", "");
+        let b = release("2023-05-05", "Motley Crue", "Dr Feelgood", "");
+
+        assert_eq!(id(&a), "motley_crue-dr_feelgood");
+        assert_eq!(id(&a), id(&b));
+        assert!(same(&a, &b));
+        assert!(!same(
+            &a,
+            &release("2023-04-14", "Motley Crue", "Shout at the Devil", "")
+        ));
+    }
+
+    #[test]
+    fn merge_fetched_keeps_state() {
+        let mut enriched = release("2023-04-14", "Metallica", "72 Seasons", "Blackened");
+        enriched.spotify = spotify();
+        enriched.triage = Some(Triage::Want);
+
+        let mut skipped = release("2023-04-14", "Nickelback", "Get Rollin'", "BMG");
+        skipped.skip = true;
+
+        let stored = vec![
+            enriched,
+            skipped,
+            release("2023-04-07", "Gone", "Unenriched", ""),
+        ];
+        let fetched = vec![
+            release("2023-04-14", "Metallica", "72 Seasons (Deluxe)", ""),
+            release("2023-04-14", "Metallica", "72 Seasons", ""),
+            release("2023-04-21", "Enslaved", "Heimdal", "Nuclear Blast"),
+        ];
+
+        let (merged, notices) = merge_fetched(stored, fetched);
+        let ids: Vec<String> = merged.iter().map(id).collect();
+
+        assert_eq!(
+            ids,
+            vec![
+                "metallica-72_seasons",
+                "nickelback-get_rollin",
+                "enslaved-heimdal"
+            ]
+        );
+        assert_eq!(merged[0].spotify, spotify());
+        assert_eq!(merged[0].triage, Some(Triage::Want));
+        // An empty label in the fetch doesn't wipe the stored one
+        assert_eq!(merged[0].label, "Blackened");
+        assert!(merged[1].skip);
+        assert!(notices.is_empty());
+    }
+
+    #[test]
+    fn merge_fetched_moves_date() {
+        let mut stored = release("2023-04-14", "Metallica", "72 Seasons", "Blackened");
+        stored.spotify = spotify();
+
+        let (merged, notices) = merge_fetched(
+            vec![stored],
+            vec![release(
+                "2023-05-05",
+                "Metallica",
+                "72 Seasons",
+                "Blackened",
+            )],
+        );
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].date.to_string(), "2023-05-05");
+        assert_eq!(merged[0].spotify, spotify());
+        assert_eq!(merged[0].status, Some(ReleaseStatus::Postponed));
+        assert_eq!(merged[0].date_history[0].from.to_string(), "2023-04-14");
+        assert_eq!(
+            notices[0].text,
+            "Metallica - 72 Seasons moved from Apr 14 to May 5"
+        );
+
+        // Moving back to the original date isn't a postponement
+        let (merged, _) = merge_fetched(
+            merged,
+            vec![release(
+                "2023-04-14",
+                "Metallica",
+                "72 Seasons",
+                "Blackened",
+            )],
+        );
+
+        assert_eq!(merged[0].status, None);
+        assert_eq!(merged[0].date_history.len(), 2);
+    }
+
+    #[test]
+    fn merge_fetched_cancels() {
+        let upcoming = (Local::now().date_naive() + chrono::Duration::days(7))
+            .format("%Y-%m-%d")
+            .to_string();
+
+        let mut followed = release(&upcoming, "Metallica", "72 Seasons", "Blackened");
+        followed.followed = true;
+
+        let mut skipped = release(&upcoming, "Nickelback", "Get Rollin'", "BMG");
+        skipped.spotify = spotify();
+        skipped.skip = true;
+
+        let stored = vec![
+            followed,
+            skipped,
+            release(&upcoming, "Gone", "Unenriched", ""),
+            release("2023-04-14", "Enslaved", "Heimdal", "Nuclear Blast"),
+        ];
+
+        let (merged, notices) = merge_fetched(stored, vec![]);
+
+        // The past, unenriched release is dropped
+        assert_eq!(merged.len(), 3);
+        assert!(merged
+            .iter()
+            .all(|r| r.status == Some(ReleaseStatus::Cancelled)));
+        // Only the release that would have been posted is announced
+        assert_eq!(notices.len(), 1);
+        assert_eq!(notices[0].release.artist, "Metallica");
+
+        let merged: Vec<Release> = merged
+            .into_iter()
+            .filter(|r| r.artist == "Metallica")
+            .collect();
+
+        // Back on the calendar
+        let (merged, notices) = merge_fetched(
+            merged,
+            vec![release(&upcoming, "Metallica", "72 Seasons", "Blackened")],
+        );
+
+        assert_eq!(merged[0].status, None);
+        assert!(notices.is_empty());
+    }
+
+    #[test]
+    fn stored_valid_leaves_out_cancelled() {
+        let enriched = |artist: &str, status: Option<ReleaseStatus>| {
+            let mut r = release("2023-04-14", artist, "Album", "");
+            r.spotify = spotify();
+            r.metallum = Some(MetallumArtistInfo::default());
+            r.status = status;
+            r
+        };
+
+        let config = Config {
+            releases: vec![
+                enriched("Metallica", None),
+                enriched("Enslaved", Some(ReleaseStatus::Postponed)),
+                enriched("Opeth", Some(ReleaseStatus::Cancelled)),
+                release("2023-04-14", "Gojira", "Album", ""),
+            ],
+            ..Default::default()
+        };
+
+        let artists: Vec<&str> = stored_valid_releases(&config)
+            .iter()
+            .map(|r| r.artist.as_str())
+            .collect();
+
+        assert_eq!(artists, vec!["Metallica", "Enslaved"]);
+    }
+
+    #[test]
+    fn merge_releases_upserts() {
+        let mut stored = release("2023-04-14", "Metallica", "72 Seasons", "Blackened");
+        stored.spotify = spotify();
+
+        let mut all = vec![stored];
+        let todays = vec![
+            // Spotify lookup failed this time
+            release("2023-04-14", "Metallica", "72 Seasons", "Blackened"),
+            release("2023-04-14", "Enslaved", "Heimdal", "Nuclear Blast"),
+        ];
+
+        merge_releases(&mut all, &todays);
+
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].spotify, spotify());
+        assert_eq!(id(&all[1]), "enslaved-heimdal");
+        assert!(all.iter().all(|r| r.looked_up));
+    }
+}
@@ -1,6 +1,6 @@
-use crate::AppError;
+use crate::{normalize, AppError};
 // use log::{debug, info};
-use rspotify::model::{Page, SearchResult};
+use rspotify::model::{ArtistId, Page, SearchResult};
 use rspotify::{model::FullArtist, model::SearchType, prelude::*, ClientCredsSpotify, Credentials};
 
 pub struct Spotify {
@@ -40,6 +40,16 @@ impl Spotify {
         Ok(self.filter_artists(artist_name, &artists))
     }
 
+    // None if the ID isn't a Spotify artist ID
+    pub async fn artist_name(&self, id: &str) -> Result<Option<String>, AppError> {
+        let id = match ArtistId::from_id(id) {
+            Ok(id) => id,
+            Err(_) => return Ok(None),
+        };
+
+        Ok(Some(self.client.artist(id).await?.name))
+    }
+
     /// Improve the results by reducing the number of bad matches
     fn filter_artists(&self, artist_name: &str, artists: &Page<FullArtist>) -> Vec<FullArtist> {
         let mut filtered_artists: Vec<FullArtist> = Vec::new();
@@ -57,7 +67,7 @@ impl Spotify {
                 break;
             }
 
-            if artist.name.to_lowercase() == artist_name.to_lowercase() {
+            if normalize::same_artist(&artist.name, artist_name) {
                 // debug!("Found a perfect artist name match for '{}'", artist_name);
                 filtered_artists.push(artist.clone());
                 continue;
@@ -0,0 +1,66 @@
+use crate::bot::shutdown_signal;
+use crate::config::{self, Config};
+use crate::AppError;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+// A document rendered from the release store
+#[derive(Clone, Copy)]
+pub struct Document {
+    pub path: &'static str,
+    pub content_type: &'static str,
+    pub render: fn(&Config) -> String,
+}
+
+// Serves a document on its path (and on /); the release store is re-read on
+// every request so subscribers pick up releases from later runs
+pub async fn serve(addr: SocketAddr, document: Document) -> Result<(), AppError> {
+    let make_svc = make_service_fn(move |_conn| async move {
+        Ok::<_, Infallible>(service_fn(move |req| handle(req, document)))
+    });
+
+    info!("Serving on http://{}{}", addr, document.path);
+
+    Server::try_bind(&addr)?
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    Ok(())
+}
+
+async fn handle(req: Request<Body>, document: Document) -> Result<Response<Body>, Infallible> {
+    let mut resp = Response::new(Body::empty());
+
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        *resp.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return Ok(resp);
+    }
+
+    if req.uri().path() != "/" && req.uri().path() != document.path {
+        *resp.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(resp);
+    }
+
+    match config::load_config() {
+        Ok(config) => {
+            if req.method() == Method::GET {
+                *resp.body_mut() = Body::from((document.render)(&config));
+            }
+
+            resp.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static(document.content_type),
+            );
+        }
+        Err(e) => {
+            error!("Could not load release store: {}", e);
+            *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    Ok(resp)
+}
@@ -0,0 +1,540 @@
+use crate::config::{Config, Release};
+use crate::export::html_escape;
+use crate::{feedback, normalize, AppError};
+use chrono::{Datelike, NaiveDate};
+use log::{debug, info};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:70em;margin:auto;padding:1em}\
+table{border-collapse:collapse;width:100%}\
+td,th{border-bottom:1px solid #ddd;padding:.3em;text-align:left;vertical-align:top}\
+tr.skipped{color:#999}\
+img{max-width:100%}";
+
+// Relative path -> page content
+type Pages = BTreeMap<String, String>;
+
+// Releases grouped by artist, genre, label or country
+type Groups<'a> = BTreeMap<String, Vec<&'a Release>>;
+
+// (section, name) -> slug of the name's page in the section
+type Slugs = HashMap<(&'static str, String), String>;
+
+// Directories of generated pages; other files in the site directory are left alone
+const SECTIONS: [&str; 6] = ["day", "week", "artist", "genre", "label", "country"];
+
+// Writes the site into `dir`. Every page is rendered, but only new and changed
+// pages are written, so regenerating after each run is cheap on disk. Pages
+// that are no longer generated (ie. for a renamed artist) are removed.
+pub fn generate(config: &Config, dir: &Path) -> Result<usize, AppError> {
+    let pages = pages(&config.releases);
+    let mut written = 0;
+
+    for (path, content) in &pages {
+        let path = dir.join(path);
+
+        if fs::read_to_string(&path).ok().as_deref() == Some(content.as_str()) {
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        debug!("Writing '{}'", path.display());
+        fs::write(&path, content)?;
+        written += 1;
+    }
+
+    let mut removed = 0;
+
+    for section in SECTIONS {
+        let entries = match fs::read_dir(dir.join(section)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let path = format!("{}/{}", section, entry.file_name().to_string_lossy());
+
+            if path.ends_with(".html") && !pages.contains_key(&path) {
+                debug!("Removing '{}'", entry.path().display());
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+    }
+
+    info!(
+        "Generated site in '{}' ({} pages, {} updated, {} removed)",
+        dir.display(),
+        pages.len(),
+        written,
+        removed
+    );
+
+    Ok(written)
+}
+
+fn pages(releases: &[Release]) -> Pages {
+    let mut pages = Pages::new();
+
+    let mut days: BTreeMap<NaiveDate, Vec<&Release>> = BTreeMap::new();
+    let mut weeks: BTreeMap<String, Vec<&Release>> = BTreeMap::new();
+    let mut artists = Groups::new();
+    let mut genres = Groups::new();
+    let mut labels = Groups::new();
+    let mut countries = Groups::new();
+
+    for release in releases {
+        days.entry(release.date).or_default().push(release);
+        weeks.entry(week(release.date)).or_default().push(release);
+        group(&mut artists, &release.artist, release);
+
+        if !release.label.is_empty() {
+            group(&mut labels, &release.label, release);
+        }
+
+        if let Some(metallum) = &release.metallum {
+            for genre in feedback::genres(&metallum.genre) {
+                group(&mut genres, &genre, release);
+            }
+
+            if !metallum.country_origin.is_empty() {
+                group(&mut countries, &metallum.country_origin, release);
+            }
+        }
+    }
+
+    let sections = [
+        ("artist", "Artists", &artists),
+        ("genre", "Genres", &genres),
+        ("label", "Labels", &labels),
+        ("country", "Countries", &countries),
+    ];
+
+    let mut slugs = Slugs::new();
+
+    for (kind, _, groups) in sections {
+        assign_slugs(&mut slugs, kind, groups.keys());
+    }
+
+    for (date, releases) in &days {
+        pages.insert(
+            format!("day/{}.html", date),
+            page(
+                &format!("Releases on {}", date.format("%A, %B %-d %Y")),
+                1,
+                &table(1, releases, &slugs),
+            ),
+        );
+    }
+
+    for (week, releases) in &weeks {
+        pages.insert(
+            format!("week/{}.html", week),
+            page(
+                &format!("Releases in {}", week),
+                1,
+                &table(1, releases, &slugs),
+            ),
+        );
+    }
+
+    for (kind, title, groups) in sections {
+        pages.insert(
+            format!("{}/index.html", kind),
+            index(title, kind, groups, &slugs),
+        );
+
+        for (name, releases) in groups {
+            // Artist pages have their own layout
+            let content = if kind == "artist" {
+                artist(name, releases, &slugs)
+            } else {
+                page(name, 1, &table(1, releases, &slugs))
+            };
+
+            pages.insert(
+                format!("{}/{}.html", kind, slug_of(&slugs, kind, name)),
+                content,
+            );
+        }
+    }
+
+    let mut body = String::from(
+        "<p>Browse <a href=\"artist/index.html\">artists</a>, \
+         <a href=\"genre/index.html\">genres</a>, \
+         <a href=\"label/index.html\">labels</a> and \
+         <a href=\"country/index.html\">countries</a>, or \
+         <a href=\"search.html\">search</a>.</p>\n",
+    );
+
+    body += "<h2>Weeks</h2>\n<ul>\n";
+    for (week, releases) in weeks.iter().rev() {
+        body += &format!(
+            "<li><a href=\"week/{w}.html\">{w}</a> ({n})</li>\n",
+            w = week,
+            n = releases.len()
+        );
+    }
+
+    body += "</ul>\n<h2>Days</h2>\n<ul>\n";
+    for (date, releases) in days.iter().rev() {
+        body += &format!(
+            "<li><a href=\"day/{d}.html\">{d}</a> ({n})</li>\n",
+            d = date,
+            n = releases.len()
+        );
+    }
+    body += "</ul>\n";
+
+    pages.insert("index.html".to_string(), page("metalpal", 0, &body));
+    pages.insert("search.html".to_string(), search_page());
+    pages.insert("search.js".to_string(), search_index(releases, &slugs));
+
+    pages
+}
+
+fn group<'a>(groups: &mut Groups<'a>, name: &str, release: &'a Release) {
+    groups.entry(name.to_string()).or_default().push(release);
+}
+
+// Names with the same slug (ie. "AC/DC" and "AC-DC") are told apart by a number,
+// in name order
+fn assign_slugs<'a>(
+    slugs: &mut Slugs,
+    kind: &'static str,
+    names: impl Iterator<Item = &'a String>,
+) {
+    let mut taken = HashSet::new();
+
+    for name in names {
+        let base = slug(name);
+        let mut slug = base.clone();
+        let mut n = 1;
+
+        while !taken.insert(slug.clone()) {
+            n += 1;
+            slug = format!("{}-{}", base, n);
+        }
+
+        slugs.insert((kind, name.clone()), slug);
+    }
+}
+
+fn slug_of<'a>(slugs: &'a Slugs, kind: &'static str, name: &str) -> &'a str {
+    slugs
+        .get(&(kind, name.to_string()))
+        .map_or("unknown", String::as_str)
+}
+
+// ISO week, ie. "2023-W07"
+fn week(date: NaiveDate) -> String {
+    let week = date.iso_week();
+    format!("{}-W{:02}", week.year(), week.week())
+}
+
+// Lowercase letters and digits separated by dashes; diacritics are folded and
+// other scripts kept, ie. "Mgła" -> "mgla"
+fn slug(name: &str) -> String {
+    let slug = normalize::text(name).replace(' ', "-");
+
+    if slug.is_empty() {
+        "unknown".to_string()
+    } else {
+        slug
+    }
+}
+
+// Pages are either at the root (depth 0) or in a section directory (depth 1)
+fn page(title: &str, depth: usize, body: &str) -> String {
+    let root = "../".repeat(depth);
+
+    format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head><meta charset=\"utf-8\"><title>{title}</title><style>{style}</style></head>\n\
+         <body>\n\
+         <nav><a href=\"{root}index.html\">metalpal</a> | <a href=\"{root}search.html\">search</a></nav>\n\
+         <h1>{title}</h1>\n\
+         {body}\
+         </body>\n\
+         </html>\n",
+        title = html_escape(title),
+        style = STYLE,
+        root = root,
+        body = body
+    )
+}
+
+fn table(depth: usize, releases: &[&Release], slugs: &Slugs) -> String {
+    let root = "../".repeat(depth);
+    let link = |kind: &'static str, name: &str| {
+        format!(
+            "<a href=\"{}{}/{}.html\">{}</a>",
+            root,
+            kind,
+            html_escape(slug_of(slugs, kind, name)),
+            html_escape(name)
+        )
+    };
+
+    let mut rows = String::new();
+
+    for release in releases {
+        let (genre, country) = match &release.metallum {
+            Some(m) => (
+                feedback::genres(&m.genre)
+                    .iter()
+                    .map(|g| link("genre", g))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                link("country", &m.country_origin),
+            ),
+            None => (String::new(), String::new()),
+        };
+
+        let mut links = vec![];
+
+        if let Some(metallum) = &release.metallum {
+            links.push(format!(
+                "<a href=\"{}\">Metal Archives</a>",
+                html_escape(&metallum.url)
+            ));
+        }
+
+        if let Some(spotify) = &release.spotify {
+            links.push(format!(
+                "<a href=\"{}\">Spotify</a>",
+                html_escape(&spotify.url)
+            ));
+        }
+
+        rows += &format!(
+            "<tr{class}><td><a href=\"{root}day/{date}.html\">{date}</a></td><td>{artist}</td>\
+             <td>{album}</td><td>{genre}</td><td>{country}</td><td>{label}</td><td>{links}</td>\
+             <td>{reasons}</td></tr>\n",
+            class = if release.skip {
+                " class=\"skipped\""
+            } else {
+                ""
+            },
+            root = root,
+            date = release.date,
+            artist = link("artist", &release.artist),
+            album = html_escape(&release.album),
+            genre = genre,
+            country = country,
+            label = if release.label.is_empty() {
+                String::new()
+            } else {
+                link("label", &release.label)
+            },
+            links = links.join(" "),
+            reasons = html_escape(&release.skip_reasons.join("; ")),
+        );
+    }
+
+    format!(
+        "<table>\n\
+         <tr><th>Date</th><th>Artist</th><th>Album</th><th>Genre</th><th>Country</th>\
+         <th>Label</th><th>Links</th><th>Skipped because</th></tr>\n\
+         {}\
+         </table>\n",
+        rows
+    )
+}
+
+fn index(title: &str, kind: &'static str, groups: &Groups, slugs: &Slugs) -> String {
+    let mut body = String::from("<ul>\n");
+
+    for (name, releases) in groups {
+        body += &format!(
+            "<li><a href=\"{}.html\">{}</a> ({})</li>\n",
+            html_escape(slug_of(slugs, kind, name)),
+            html_escape(name),
+            releases.len()
+        );
+    }
+
+    body += "</ul>\n";
+
+    page(title, 1, &body)
+}
+
+// Bio and image come from the most recently enriched release
+fn artist(name: &str, releases: &[&Release], slugs: &Slugs) -> String {
+    let mut body = String::new();
+
+    if let Some(m) = releases.iter().rev().find_map(|r| r.metallum.as_ref()) {
+        if !m.band_img_url.is_empty() {
+            body += &format!(
+                "<img src=\"{}\" alt=\"{}\">\n",
+                html_escape(&m.band_img_url),
+                html_escape(name)
+            );
+        }
+
+        body += "<dl>\n";
+
+        for (term, value) in [
+            ("Country", &m.country_origin),
+            ("Location", &m.locations),
+            ("Formed in", &m.formed_in),
+            ("Years active", &m.years_active),
+            ("Genre", &m.genre),
+            ("Themes", &m.themes),
+            ("Status", &m.status),
+            ("Last label", &m.last_label),
+        ] {
+            if !value.is_empty() {
+                body += &format!("<dt>{}</dt><dd>{}</dd>\n", term, html_escape(value));
+            }
+        }
+
+        body += "</dl>\n";
+
+        let bio = if m.description_long.is_empty() {
+            &m.description_short
+        } else {
+            &m.description_long
+        };
+
+        if !bio.is_empty() {
+            body += &format!("<p>{}</p>\n", html_escape(bio).replace('\n', "<br>"));
+        }
+
+        body += &format!(
+            "<p><a href=\"{}\">Metal Archives</a></p>\n",
+            html_escape(&m.url)
+        );
+    }
+
+    body += "<h2>Releases</h2>\n";
+    body += &table(1, releases, slugs);
+
+    page(name, 1, &body)
+}
+
+// Loaded with a script tag rather than fetched so search also works when the
+// site is opened from disk
+fn search_index(releases: &[Release], slugs: &Slugs) -> String {
+    let entries: Vec<_> = releases
+        .iter()
+        .map(|r| {
+            json!({
+                "date": r.date,
+                "artist": r.artist,
+                "album": r.album,
+                "label": r.label,
+                "genre": r.metallum.as_ref().map(|m| m.genre.as_str()).unwrap_or_default(),
+                "url": format!("artist/{}.html", slug_of(slugs, "artist", &r.artist)),
+            })
+        })
+        .collect();
+
+    format!("const RELEASES = {};\n", json!(entries))
+}
+
+fn search_page() -> String {
+    page(
+        "Search",
+        0,
+        "<input id=\"q\" type=\"search\" placeholder=\"Artist, album, genre or label\" autofocus>\n\
+         <ul id=\"results\"></ul>\n\
+         <script src=\"search.js\"></script>\n\
+         <script>\n\
+         const q = document.getElementById('q');\n\
+         const results = document.getElementById('results');\n\
+         q.addEventListener('input', () => {\n\
+           const terms = q.value.toLowerCase().split(/\\s+/).filter(t => t);\n\
+           results.replaceChildren();\n\
+           if (!terms.length) return;\n\
+           RELEASES.filter(r => {\n\
+             const text = [r.artist, r.album, r.genre, r.label].join(' ').toLowerCase();\n\
+             return terms.every(t => text.includes(t));\n\
+           }).slice(0, 100).forEach(r => {\n\
+             const li = document.createElement('li');\n\
+             const a = document.createElement('a');\n\
+             a.href = r.url;\n\
+             a.textContent = `${r.artist} - ${r.album}`;\n\
+             li.append(a, ` (${r.date}, ${r.genre})`);\n\
+             results.append(li);\n\
+           });\n\
+         });\n\
+         </script>\n",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn release(artist: &str) -> Release {
+        serde_json::from_value(json!({
+            "date": "2023-04-14",
+            "artist": artist,
+            "album": "Album",
+            "label": "",
+            "skip_reasons": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("Mgła"), "mgla");
+        assert_eq!(slug("Mötley Crüe"), "motley-crue");
+        assert_eq!(slug("Сатанакозёл"), "сатанакозел");
+        assert_eq!(slug("陰陽座"), "陰陽座");
+        assert_eq!(slug("!!!"), "unknown");
+    }
+
+    #[test]
+    fn colliding_names() {
+        let releases = vec![release("AC/DC"), release("AC-DC"), release("Mgła")];
+        let pages = pages(&releases);
+
+        assert!(pages.contains_key("artist/ac-dc.html"));
+        assert!(pages.contains_key("artist/ac-dc-2.html"));
+        assert!(pages.contains_key("artist/mgla.html"));
+
+        // Links go to each name's own page
+        let day = &pages["day/2023-04-14.html"];
+        assert!(day.contains("<a href=\"../artist/ac-dc-2.html\">AC/DC</a>"));
+        assert!(day.contains("<a href=\"../artist/ac-dc.html\">AC-DC</a>"));
+        assert!(pages["search.js"].contains("\"url\":\"artist/ac-dc-2.html\""));
+    }
+
+    #[test]
+    fn stale_pages_are_removed() {
+        let dir = std::env::temp_dir().join(format!("metalpal-site-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        fs::create_dir_all(dir.join("artist")).unwrap();
+        fs::write(dir.join("artist/renamed.html"), "").unwrap();
+        fs::write(dir.join("style.css"), "").unwrap();
+
+        let config = Config {
+            releases: vec![release("Enslaved")],
+            ..Default::default()
+        };
+
+        generate(&config, &dir).unwrap();
+
+        assert!(dir.join("artist/enslaved.html").exists());
+        assert!(!dir.join("artist/renamed.html").exists());
+        // Files metalpal didn't generate are left alone
+        assert!(dir.join("style.css").exists());
+
+        // Nothing changed, nothing written
+        assert_eq!(generate(&config, &dir).unwrap(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
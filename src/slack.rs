@@ -1,102 +1,1030 @@
-use crate::config::Release;
-use crate::{config, AppError};
+use crate::config::{PostedMessage, Release, Subscription, SubscriptionTarget};
+use crate::notifier::{Notice, Notifier};
+use crate::{config, export, feedback, release, subscription, AppError};
+use async_trait::async_trait;
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use futures_util::TryStreamExt;
+use log::{debug, info, warn};
 use slack::attachment::attachment::{Attachment, AttachmentField};
+use slack::auth::test::test;
+use slack::chat::delete_scheduled_message::{
+    delete_scheduled_message, DeleteScheduledMessageRequest,
+};
 use slack::chat::post_message::{post_message, PostMessageRequest};
-use slack::http_client::default_client;
+use slack::chat::schedule_message::{scheduled_message, ScheduledMessageRequest};
+use slack::chat::scheduled_messages_list::{
+    scheduled_messages_list, ScheduledMessage, ScheduledMessagesListRequest,
+};
+use slack::conversations::history::{history, HistoryRequest};
+use slack::conversations::info::{info, InfoRequest};
+use slack::conversations::join::{join, JoinRequest};
+use slack::conversations::list::{list_all, ListRequest};
+use slack::conversations::open::{open, OpenRequest};
+use slack::error::Error as SlackAPIError;
+use slack::files::upload_v2::{upload_v2, UploadV2Request};
+use slack::http_client::Client;
+use slack::pagination::PageOptions;
+use slack::rate_limit::{default_rate_limited_client, RateLimitedClient};
+use slack::reactions::get::{get, GetRequest};
+use slack::users::info::{info as user_info, InfoRequest as UserInfoRequest};
 use slack_rust as slack;
+use std::collections::HashMap;
+use std::sync::Mutex;
 
 pub struct Slack {
+    client: RateLimitedClient<Client>,
     token: String,
     channels: Vec<String>,
+    subscriptions: Vec<Subscription>,
+    delivery_time: Option<String>,
+    timezone: Option<String>,
+    join_channels: bool,
+    upload_images: bool,
+    weekly_export: Option<String>,
+    // Channel (as configured) -> channel ID; filled in by preflight()
+    channel_ids: HashMap<String, String>,
+    // Public channels preflight() found the bot has to join; joined before
+    // the first post
+    to_join: Mutex<Vec<String>>,
+    // Release messages posted via notify()
+    posted: Mutex<Vec<PostedMessage>>,
 }
 
 impl Slack {
     pub fn new(cfg: &config::Config) -> Self {
         Self {
+            client: default_rate_limited_client(),
             token: cfg.slack_bot_token.clone(),
             channels: cfg.slack_channels.clone(),
+            subscriptions: cfg.subscriptions.clone(),
+            delivery_time: cfg.slack_delivery_time.clone(),
+            timezone: cfg.slack_timezone.clone(),
+            join_channels: cfg.slack_join_channels,
+            upload_images: cfg.slack_upload_images,
+            weekly_export: cfg.slack_weekly_export.clone(),
+            channel_ids: HashMap::new(),
+            to_join: Mutex::new(vec![]),
+            posted: Mutex::new(vec![]),
         }
     }
 
-    pub async fn post_releases(&self, releases: &Vec<&Release>) -> Result<(), AppError> {
-        let slack_api_client = default_client();
-        let unix_ts = chrono::Local::now().timestamp() as i32;
+    // Release messages posted since the last call
+    pub fn take_posted(&self) -> Vec<PostedMessage> {
+        std::mem::take(&mut *self.posted.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    // Posted release messages are recorded as they go, so reactions on them can
+    // be read later even if a later post fails
+    pub async fn post_releases(&self, releases: &[&Release]) -> Result<(), AppError> {
+        let post_at = self.post_at()?;
 
+        // Every destination is resolved before anything is posted, so a failure
+        // can't leave the digest half posted
+        let mut digests = vec![];
+
+        // Configured channels get everything
         for channel in &self.channels {
+            let header = format!(
+                ":tada: There are *{}* releases today! :tada:",
+                releases.len()
+            );
+
+            digests.push((
+                self.channel_id(channel)?.to_string(),
+                header,
+                releases.to_vec(),
+            ));
+        }
+
+        // Subscribers only get what matches their filter profile
+        for sub in &self.subscriptions {
+            let matching = subscription::releases_for(sub, releases);
+
+            if matching.is_empty() {
+                debug!("No releases today for subscription '{}'", sub.name);
+                continue;
+            }
+
+            let header = format!(
+                ":tada: There are *{}* releases today matching your *{}* subscription! :tada:",
+                matching.len(),
+                sub.name
+            );
+
+            info!(
+                "Sending {} releases to subscription '{}'",
+                matching.len(),
+                sub.name
+            );
+
+            digests.push((self.destination(&sub.target).await?, header, matching));
+        }
+
+        self.join_channels().await?;
+
+        for (channel, header, releases) in digests {
+            self.post_digest(&channel, &header, &releases, post_at)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Notices are posted right away as one message per channel; subscribers only
+    // get notices about releases matching their filter profile
+    pub async fn post_notices(&self, notices: &[&Notice]) -> Result<(), AppError> {
+        let text = |notices: &[&Notice]| {
+            notices
+                .iter()
+                .map(|n| format!("{} {}", n.emoji(), n.text))
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+
+        let mut messages = vec![];
+
+        for channel in &self.channels {
+            messages.push((self.channel_id(channel)?.to_string(), text(notices)));
+        }
+
+        for sub in &self.subscriptions {
+            let matching: Vec<&Notice> = notices
+                .iter()
+                .filter(|n| subscription::matches(&sub.filter, &n.release))
+                .copied()
+                .collect();
+
+            if matching.is_empty() {
+                continue;
+            }
+
+            messages.push((self.destination(&sub.target).await?, text(&matching)));
+        }
+
+        self.join_channels().await?;
+
+        for (channel, text) in messages {
+            let param = PostMessageRequest {
+                channel,
+                text: Some(text),
+                ..Default::default()
+            };
+
+            post_message(&self.client, &param, &self.token).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_scheduled(&self) -> Result<Vec<ScheduledMessage>, AppError> {
+        let resp = scheduled_messages_list(
+            &self.client,
+            &ScheduledMessagesListRequest::default(),
+            &self.token,
+        )
+        .await?;
+
+        Ok(resp.scheduled_messages.unwrap_or_default())
+    }
+
+    pub async fn cancel_scheduled(&self, channel: &str, id: &str) -> Result<(), AppError> {
+        let param = DeleteScheduledMessageRequest {
+            channel: channel.to_string(),
+            scheduled_message_id: id.to_string(),
+            ..Default::default()
+        };
+
+        delete_scheduled_message(&self.client, &param, &self.token).await?;
+
+        Ok(())
+    }
+
+    // Returns the unix timestamp digests should be scheduled for; None means
+    // post right away (no delivery time configured or it has already passed today)
+    fn post_at(&self) -> Result<Option<i64>, AppError> {
+        let delivery_time = match &self.delivery_time {
+            Some(t) => NaiveTime::parse_from_str(t, "%H:%M")?,
+            None => return Ok(None),
+        };
+
+        let post_at = match &self.timezone {
+            Some(tz) => {
+                let tz: Tz = tz.parse().map_err(|e| {
+                    AppError::GenericError(format!("Invalid timezone '{}': {}", tz, e))
+                })?;
+
+                timestamp_today(tz, delivery_time)
+            }
+            None => timestamp_today(Local, delivery_time),
+        }
+        .ok_or_else(|| {
+            AppError::GenericError(format!(
+                "Delivery time '{}' does not exist today",
+                delivery_time
+            ))
+        })?;
+
+        if post_at <= Utc::now().timestamp() {
+            info!(
+                "Delivery time '{}' has already passed today; posting immediately",
+                delivery_time
+            );
+
+            return Ok(None);
+        }
+
+        Ok(Some(post_at))
+    }
+
+    // Times of the messages already scheduled in the channel, or None if an
+    // earlier run has scheduled this digest (its header is due on the same day)
+    async fn scheduled_times(
+        &self,
+        channel: &str,
+        header: &str,
+        post_at: i64,
+    ) -> Result<Option<Vec<i64>>, AppError> {
+        let param = ScheduledMessagesListRequest {
+            channel: Some(channel.to_string()),
+            ..Default::default()
+        };
+
+        let resp = scheduled_messages_list(&self.client, &param, &self.token).await?;
+        let scheduled = resp.scheduled_messages.unwrap_or_default();
+
+        let same_day = |t: i64| t >= post_at && t < post_at + Duration::days(1).num_seconds();
+
+        if scheduled.iter().any(|m| {
+            m.text.as_deref() == Some(header) && m.post_at.is_some_and(|t| same_day(t as i64))
+        }) {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            scheduled
+                .iter()
+                .filter_map(|m| m.post_at.map(i64::from))
+                .collect(),
+        ))
+    }
+
+    // Posts immediately or schedules the message for `post_at`; returns the
+    // ts of messages that were posted immediately
+    async fn send(
+        &self,
+        param: PostMessageRequest,
+        post_at: Option<i64>,
+    ) -> Result<Option<String>, AppError> {
+        let post_at = match post_at {
+            Some(post_at) => post_at,
+            None => {
+                let resp = post_message(&self.client, &param, &self.token).await?;
+                return Ok(resp.ts);
+            }
+        };
+
+        let param = ScheduledMessageRequest {
+            channel: param.channel,
+            post_at: post_at as i32,
+            text: param.text.unwrap_or_default(),
+            attachments: param.attachments,
+            ..Default::default()
+        };
+
+        scheduled_message(&self.client, &param, &self.token).await?;
+
+        Ok(None)
+    }
+
+    // Configured channels plus channels subscriptions are delivered to
+    fn channel_targets(&self) -> Vec<String> {
+        let mut targets: Vec<String> = self
+            .channels
+            .iter()
+            .chain(
+                self.subscriptions
+                    .iter()
+                    .filter_map(|sub| match &sub.target {
+                        SubscriptionTarget::Channel(channel) => Some(channel),
+                        SubscriptionTarget::User(_) => None,
+                    }),
+            )
+            .cloned()
+            .collect();
+
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+
+    // Users subscriptions are delivered to by DM
+    fn user_targets(&self) -> Vec<String> {
+        let mut targets: Vec<String> = self
+            .subscriptions
+            .iter()
+            .filter_map(|sub| match &sub.target {
+                SubscriptionTarget::User(user) => Some(user.clone()),
+                SubscriptionTarget::Channel(_) => None,
+            })
+            .collect();
+
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+
+    fn channel_id(&self, channel: &str) -> Result<&str, AppError> {
+        self.channel_ids
+            .get(channel)
+            .map(|id| id.as_str())
+            .ok_or_else(|| {
+                AppError::SlackError(format!("Channel '{}' has not been resolved", channel))
+            })
+    }
+
+    // The channel ID a subscription is delivered to
+    async fn destination(&self, target: &SubscriptionTarget) -> Result<String, AppError> {
+        match target {
+            SubscriptionTarget::Channel(channel) => Ok(self.channel_id(channel)?.to_string()),
+            SubscriptionTarget::User(user) => self.open_dm(user).await,
+        }
+    }
+
+    // Maps channels to their IDs; names (`#metal` or `metal`) are looked up
+    // via conversations.list, IDs are used as is. Also returns the channels
+    // that could not be found.
+    async fn resolve_channels(
+        &self,
+        channels: &[String],
+    ) -> Result<(HashMap<String, String>, Vec<String>), AppError> {
+        let (mut resolved, mut names) = split_channels(channels);
+
+        if names.is_empty() {
+            return Ok((resolved, vec![]));
+        }
+
+        let param = ListRequest {
+            exclude_archived: Some(true),
+            types: Some("public_channel,private_channel".to_string()),
+            ..Default::default()
+        };
+        let options = PageOptions::builder().page_size(200).build();
+        let mut conversations = Box::pin(list_all(&self.client, param, options, &self.token));
+
+        while let Some(conversation) = conversations.try_next().await? {
+            let (name, id) = match (conversation.name, conversation.id) {
+                (Some(name), Some(id)) => (name, id),
+                _ => continue,
+            };
+
+            for channel in names.remove(&name).unwrap_or_default() {
+                debug!("Resolved channel '{}' to '{}'", channel, id);
+                resolved.insert(channel, id.clone());
+            }
+
+            // Stop paging once everything is resolved
+            if names.is_empty() {
+                break;
+            }
+        }
+
+        let mut missing: Vec<String> = names.into_values().flatten().collect();
+        missing.sort();
+
+        Ok((resolved, missing))
+    }
+
+    // Whether the bot can post to the channel; only looks, public channels are
+    // joined by join_channels() when `slack_join_channels` is set
+    async fn check_membership(&self, id: &str) -> Result<Membership, AppError> {
+        // DMs are opened on demand
+        if id.starts_with('D') {
+            return Ok(Membership::Member);
+        }
+
+        let param = InfoRequest {
+            channel: id.to_string(),
+            ..Default::default()
+        };
+
+        let channel = match info(&self.client, &param, &self.token).await {
+            Ok(resp) => resp.channel.unwrap_or_default(),
+            // ie. channel_not_found for private channels the bot isn't in
+            Err(SlackAPIError::SlackAPIError(e)) => return Ok(Membership::Denied(e)),
+            Err(e) => return Err(e.into()),
+        };
+
+        if channel.is_archived == Some(true) {
+            return Ok(Membership::Denied("channel is archived".to_string()));
+        }
+
+        if channel.is_member == Some(true) {
+            return Ok(Membership::Member);
+        }
+
+        if !self.join_channels || channel.is_private == Some(true) {
+            return Ok(Membership::Denied(
+                "bot is not a member; invite it to the channel (or set slack_join_channels for public channels)"
+                    .to_string(),
+            ));
+        }
+
+        Ok(Membership::Joinable)
+    }
+
+    // Joins the channels preflight() found the bot isn't in yet
+    async fn join_channels(&self) -> Result<(), AppError> {
+        let to_join = std::mem::take(&mut *self.to_join.lock().unwrap_or_else(|e| e.into_inner()));
+
+        for id in to_join {
+            let param = JoinRequest {
+                channel: id.clone(),
+            };
+
+            match join(&self.client, &param, &self.token).await {
+                Ok(_) => info!("Joined channel '{}'", id),
+                Err(SlackAPIError::SlackAPIError(e)) => {
+                    return Err(AppError::SlackError(format!(
+                        "Could not join channel '{}': {}",
+                        id, e
+                    )))
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns why the bot can't DM the user, if anything
+    async fn check_user(&self, user: &str) -> Result<Option<String>, AppError> {
+        let param = UserInfoRequest {
+            user: user.to_string(),
+            ..Default::default()
+        };
+
+        let user = match user_info(&self.client, &param, &self.token).await {
+            Ok(resp) => resp.user.unwrap_or_default(),
+            // ie. user_not_found
+            Err(SlackAPIError::SlackAPIError(e)) => return Ok(Some(e)),
+            Err(e) => return Err(e.into()),
+        };
+
+        if user.deleted == Some(true) {
+            return Ok(Some("user is deactivated".to_string()));
+        }
+
+        if user.is_bot == Some(true) {
+            return Ok(Some("user is a bot".to_string()));
+        }
+
+        Ok(None)
+    }
+
+    // Opens (or resumes) a DM with the user and returns its channel ID
+    async fn open_dm(&self, user: &str) -> Result<String, AppError> {
+        let param = OpenRequest {
+            users: Some(user.to_string()),
+            ..Default::default()
+        };
+
+        let resp = open(&self.client, &param, &self.token).await?;
+
+        resp.channel
+            .and_then(|c| c.id)
+            .ok_or_else(|| AppError::SlackError(format!("Could not open DM with user '{}'", user)))
+    }
+
+    async fn post_digest(
+        &self,
+        channel: &str,
+        header: &str,
+        releases: &[&Release],
+        post_at: Option<i64>,
+    ) -> Result<(), AppError> {
+        let unix_ts = post_at.unwrap_or_else(|| Local::now().timestamp()) as i32;
+
+        // Scheduled messages with the same post_at are not guaranteed to be
+        // delivered in order, so each message gets its own slot after the last
+        let mut slots = match post_at {
+            Some(post_at) => match self.scheduled_times(channel, header, post_at).await? {
+                Some(taken) => Some((taken, post_at)),
+                None => {
+                    info!(
+                        "Digest for channel '{}' is already scheduled; skipping",
+                        channel
+                    );
+
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+
+        let mut next_post_at = || {
+            slots.as_mut().map(|(taken, from)| {
+                let slot = next_slot(taken, *from);
+                taken.push(slot);
+                *from = slot + 1;
+                slot
+            })
+        };
+
+        let param = PostMessageRequest {
+            channel: channel.to_string(),
+            text: Some(header.to_string()),
+            ..Default::default()
+        };
+
+        self.send(param, next_post_at()).await?;
+
+        // Uploaded files can't be scheduled, so only upload when posting right away
+        let upload_images = self.upload_images && post_at.is_none();
+
+        for (i, release) in releases.iter().enumerate() {
+            // Followed releases may not have been enriched
+            let spotify_metadata = release.spotify.clone().unwrap_or_default();
+            let metallum_metadata = release.metallum.clone().unwrap_or_default();
+
+            let star = if release.followed { ":star: " } else { "" };
+
             let param = PostMessageRequest {
                 channel: channel.to_string(),
-                text: Some(format!(
-                    ":tada: There are *{}* releases today! :tada:",
-                    releases.len()
-                )),
+                attachments: Some(vec![Attachment {
+                    color: Some("#36a64f".to_string()),
+                    title: Some(format!(
+                        "{}{}. {} - {}",
+                        star,
+                        i + 1,
+                        release.artist,
+                        release.album
+                    )),
+                    title_link: Some(metallum_metadata.url.clone()),
+                    // Too much data in output - would be nice if there was a way to collapse a section by default.
+                    // text: Some(format!("\n\n{}\n\n{}", metallum_metadata.description_short.clone(), metallum_metadata.img_url.clone())),
+
+                    // Hmmm... thumb doesn't get generated for some reason? Image url works though
+                    // thumb_url: Some(metallum_metadata.band_name_img_url.clone()),
+                    image_url: if upload_images {
+                        None
+                    } else {
+                        Some(metallum_metadata.band_img_url.clone())
+                    },
+                    fields: Some(vec![
+                        AttachmentField {
+                            title: Some("Release Date".to_string()),
+                            value: Some(release.date.to_string()),
+                            short: Some(true),
+                        },
+                        AttachmentField {
+                            title: Some("Genres".to_string()),
+                            value: Some(metallum_metadata.genre.clone()),
+                            short: Some(true),
+                        },
+                        AttachmentField {
+                            title: Some("Country".to_string()),
+                            value: Some(metallum_metadata.country_origin.clone()),
+                            short: Some(true),
+                        },
+                        AttachmentField {
+                            title: Some("Spotify Popularity".to_string()),
+                            value: Some(spotify_metadata.popularity.to_string()),
+                            short: Some(true),
+                        },
+                        AttachmentField {
+                            title: Some("Spotify Followers".to_string()),
+                            value: Some(spotify_metadata.followers.to_string()),
+                            short: Some(true),
+                        },
+                        AttachmentField {
+                            title: Some("Spotify Artist ID".to_string()),
+                            value: Some(spotify_metadata.id.clone()),
+                            short: Some(true),
+                        },
+                    ]),
+                    // footer: Some("Metalpal".to_string()),
+                    footer_icon: Some("https://emojis.slackmojis.com/emojis/images/1648645351/56886/metal.png?1648645351".to_string(), ),
+                    ts: Some(unix_ts),
+                    ..Default::default()
+                }]),
                 ..Default::default()
             };
 
-            post_message(&slack_api_client, &param, &self.token).await?;
-
-            let mut iter = 1;
-
-            for release in releases {
-                let spotify_metadata = release.spotify.clone().unwrap();
-                let metallum_metadata = release.metallum.clone().unwrap();
-
-                let param = PostMessageRequest {
-                    channel: channel.to_string(),
-                    attachments: Some(vec![Attachment {
-                        color: Some("#36a64f".to_string()),
-                        title: Some(format!("{}. {} - {}", iter, release.artist, release.album)),
-                        title_link: Some(metallum_metadata.url.clone()),
-                        // Too much data in output - would be nice if there was a way to collapse a section by default.
-                        // text: Some(format!("\n\n{}\n\n{}", metallum_metadata.description_short.clone(), metallum_metadata.img_url.clone())),
-
-                        // Hmmm... thumb doesn't get generated for some reason? Image url works though
-                        // thumb_url: Some(metallum_metadata.band_name_img_url.clone()),
-                        image_url: Some(metallum_metadata.band_img_url.clone()),
-                        fields: Some(vec![
-                            AttachmentField {
-                                title: Some("Release Date".to_string()),
-                                value: Some(release.date.to_string()),
-                                short: Some(true),
-                            },
-                            AttachmentField {
-                                title: Some("Genres".to_string()),
-                                value: Some(metallum_metadata.genre.clone()),
-                                short: Some(true),
-                            },
-                            AttachmentField {
-                                title: Some("Country".to_string()),
-                                value: Some(metallum_metadata.country_origin.clone()),
-                                short: Some(true),
-                            },
-                            AttachmentField {
-                                title: Some("Spotify Popularity".to_string()),
-                                value: Some(spotify_metadata.popularity.to_string()),
-                                short: Some(true),
-                            },
-                            AttachmentField {
-                                title: Some("Spotify Followers".to_string()),
-                                value: Some(spotify_metadata.followers.to_string()),
-                                short: Some(true),
-                            },
-                            AttachmentField {
-                                title: Some("Spotify Artist ID".to_string()),
-                                value: Some(spotify_metadata.id.clone()),
-                                short: Some(true),
-                            },
-                        ]),
-                        // footer: Some("Metalpal".to_string()),
-                        footer_icon: Some("https://emojis.slackmojis.com/emojis/images/1648645351/56886/metal.png?1648645351".to_string(), ),
-                        ts: Some(unix_ts.clone()),
-                        ..Default::default()
-                    }]),
+            let release_post_at = next_post_at();
+            let ts = self.send(param, release_post_at).await?;
+
+            self.posted
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(feedback::posted_message(
+                    channel,
+                    ts.clone(),
+                    release_post_at,
+                    release,
+                ));
+
+            if let (true, Some(ts), false) =
+                (upload_images, ts, metallum_metadata.band_img_url.is_empty())
+            {
+                // A missing image shouldn't hold up the rest of the digest
+                if let Err(e) = self
+                    .upload_image(channel, &ts, release, &metallum_metadata.band_img_url)
+                    .await
+                {
+                    warn!(
+                        "Could not upload image for artist '{}': {}",
+                        release.artist, e
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the reactions (name and count) on a posted release message, or
+    // None if it hasn't been delivered yet or is gone
+    pub async fn message_reactions(
+        &self,
+        message: &mut PostedMessage,
+    ) -> Result<Option<Vec<(String, i32)>>, AppError> {
+        if message.ts.is_none() {
+            message.ts = self.find_scheduled_message(message).await?;
+        }
+
+        let ts = match &message.ts {
+            Some(ts) => ts.clone(),
+            None => return Ok(None),
+        };
+
+        let param = GetRequest {
+            channel: Some(message.channel.clone()),
+            timestamp: Some(ts),
+            ..Default::default()
+        };
+
+        let resp = match get(&self.client, &param, &self.token).await {
+            Ok(resp) => resp,
+            Err(SlackAPIError::SlackAPIError(e)) if e == "message_not_found" => {
+                debug!(
+                    "Message for '{} - {}' no longer exists",
+                    message.artist, message.album
+                );
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(
+            resp.message
+                .and_then(|m| m.reactions)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|r| Some((r.name?, r.count.unwrap_or_default())))
+                .collect(),
+        ))
+    }
+
+    // Scheduled messages only get a ts once delivered; look for the release
+    // message around the time it was scheduled for
+    async fn find_scheduled_message(
+        &self,
+        message: &PostedMessage,
+    ) -> Result<Option<String>, AppError> {
+        let post_at = match message.post_at {
+            Some(post_at) if post_at < Utc::now().timestamp() => post_at,
+            _ => return Ok(None),
+        };
+
+        let param = HistoryRequest {
+            channel: message.channel.clone(),
+            oldest: Some((post_at - 60).to_string()),
+            latest: Some((post_at + 60).to_string()),
+            inclusive: Some(true),
+            ..Default::default()
+        };
+
+        let resp = history(&self.client, &param, &self.token).await?;
+
+        let title = format!("{} - {}", message.artist, message.album);
+
+        Ok(resp
+            .messages
+            .unwrap_or_default()
+            .into_iter()
+            .find(|m| {
+                m.attachments
+                    .iter()
+                    .flatten()
+                    .any(|a| a.title.as_deref().unwrap_or_default().ends_with(&title))
+            })
+            .and_then(|m| m.ts))
+    }
+
+    // Uploads the image into the release's thread
+    async fn upload_image(
+        &self,
+        channel: &str,
+        thread_ts: &str,
+        release: &Release,
+        url: &str,
+    ) -> Result<(), AppError> {
+        if url.is_empty() {
+            return Ok(());
+        }
+
+        let content = release::download_metallum_image(url).await?;
+
+        // ie. https://www.metal-archives.com/images/3/5/4/0/3540_photo.jpg?1117
+        let extension = url
+            .split('?')
+            .next()
+            .and_then(|path| path.rsplit('.').next())
+            .unwrap_or("jpg")
+            .to_lowercase();
+
+        let content_type = match extension.as_str() {
+            "png" => "image/png",
+            "gif" => "image/gif",
+            _ => "image/jpeg",
+        };
+
+        let param = UploadV2Request {
+            filename: format!("{}.{}", release.artist, extension),
+            content,
+            content_type: Some(content_type.to_string()),
+            title: Some(release.artist.clone()),
+            alt_txt: Some(release.artist.clone()),
+            channel_id: Some(channel.to_string()),
+            thread_ts: Some(thread_ts.to_string()),
+            ..Default::default()
+        };
+
+        upload_v2(&self.client, &param, &self.token).await?;
+
+        Ok(())
+    }
+
+    // The weekly export goes out once on the configured weekday
+    pub fn weekly_export_due(&self, last_export: Option<NaiveDate>) -> Result<bool, AppError> {
+        let weekday: Weekday = match &self.weekly_export {
+            Some(day) => day.parse().map_err(|_| {
+                AppError::GenericError(format!("Invalid weekly export day '{}'", day))
+            })?,
+            None => return Ok(false),
+        };
+
+        let today = Local::now().date_naive();
+
+        Ok(today.weekday() == weekday && last_export != Some(today))
+    }
+
+    // Uploads the coming week's releases as CSV and ICS to every configured channel
+    pub async fn post_weekly_export(&self, releases: &[Release]) -> Result<(), AppError> {
+        let today = Local::now().date_naive();
+        let week_end = today + Duration::days(7);
+
+        let week: Vec<&Release> = releases
+            .iter()
+            .filter(|r| r.date >= today && r.date < week_end)
+            .collect();
+
+        info!("Uploading weekly export of {} releases", week.len());
+
+        let name = format!("metalpal-{}", today.format("%Y-%m-%d"));
+        let files = [
+            (format!("{}.csv", name), "text/csv", export::csv(&week)),
+            (format!("{}.ics", name), "text/calendar", export::ics(&week)),
+        ];
+
+        for channel in &self.channels {
+            for (i, (filename, content_type, content)) in files.iter().enumerate() {
+                let param = UploadV2Request {
+                    filename: filename.clone(),
+                    content: content.as_bytes().to_vec(),
+                    content_type: Some(content_type.to_string()),
+                    title: Some(filename.clone()),
+                    channel_id: Some(self.channel_id(channel)?.to_string()),
+                    initial_comment: (i == 0).then(|| {
+                        format!(":calendar: *{}* releases coming up this week", week.len())
+                    }),
                     ..Default::default()
                 };
 
-                post_message(&slack_api_client, &param, &self.token).await?;
-                iter += 1;
+                upload_v2(&self.client, &param, &self.token).await?;
             }
         }
 
         Ok(())
     }
+
+    // Posts a plain message to a channel that isn't one of the release channels,
+    // ie. an admin channel; doesn't need preflight()
+    pub async fn post_alert(&self, channel: &str, text: &str) -> Result<(), AppError> {
+        let (resolved, missing) = self.resolve_channels(&[channel.to_string()]).await?;
+
+        let channel_id = match resolved.get(channel) {
+            Some(channel_id) => channel_id,
+            None => {
+                return Err(AppError::SlackError(format!(
+                    "Could not find channel(s): {}",
+                    missing.join(", ")
+                )))
+            }
+        };
+
+        let param = PostMessageRequest {
+            channel: channel_id.clone(),
+            text: Some(text.to_string()),
+            ..Default::default()
+        };
+
+        post_message(&self.client, &param, &self.token).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl Notifier for Slack {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    // Checks the token, resolves channel names and makes sure the bot can post
+    // to every channel and DM every subscribed user. Every problem is reported
+    // at once, before anything is sent, so a typo can't leave a digest half
+    // posted. Nothing is changed; channels to join are joined when posting.
+    async fn preflight(&mut self) -> Result<(), AppError> {
+        let identity = test(&self.client, &self.token).await?;
+
+        info!(
+            "Authenticated to Slack as '{}' in team '{}'",
+            identity.user.unwrap_or_default(),
+            identity.team.unwrap_or_default()
+        );
+
+        let (channel_ids, missing) = self.resolve_channels(&self.channel_targets()).await?;
+
+        let mut problems: Vec<String> = missing
+            .iter()
+            .map(|channel| format!("'{}': channel not found", channel))
+            .collect();
+
+        let mut to_join = vec![];
+
+        for channel in self.channel_targets() {
+            let id = match channel_ids.get(&channel) {
+                Some(id) => id,
+                None => continue,
+            };
+
+            match self.check_membership(id).await? {
+                Membership::Member => {}
+                Membership::Joinable => to_join.push(id.clone()),
+                Membership::Denied(problem) => problems.push(format!("'{}': {}", channel, problem)),
+            }
+        }
+
+        for user in self.user_targets() {
+            if let Some(problem) = self.check_user(&user).await? {
+                problems.push(format!("user '{}': {}", user, problem));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(AppError::SlackError(format!(
+                "Slack preflight failed:\n  - {}",
+                problems.join("\n  - ")
+            )));
+        }
+
+        to_join.sort();
+        to_join.dedup();
+
+        self.channel_ids = channel_ids;
+        self.to_join = Mutex::new(to_join);
+
+        Ok(())
+    }
+
+    async fn notify(&self, releases: &[&Release]) -> Result<(), AppError> {
+        self.post_releases(releases).await
+    }
+
+    async fn announce(&self, notices: &[&Notice]) -> Result<(), AppError> {
+        self.post_notices(notices).await
+    }
+}
+
+// Slack schedules at most 30 messages within 5 minutes in a channel
+const SCHEDULE_LIMIT: usize = 30;
+const SCHEDULE_WINDOW: i64 = 5 * 60;
+
+// The first time from `from` on that's free and keeps every 5 minutes around it
+// under the scheduling limit
+fn next_slot(taken: &[i64], from: i64) -> i64 {
+    let mut slot = from;
+
+    while taken.contains(&slot)
+        || taken
+            .iter()
+            .filter(|t| (slot - SCHEDULE_WINDOW < **t) && (**t < slot + SCHEDULE_WINDOW))
+            .count()
+            >= SCHEDULE_LIMIT
+    {
+        slot += 1;
+    }
+
+    slot
+}
+
+enum Membership {
+    Member,
+    // A public channel the bot may join
+    Joinable,
+    // Why the bot can't post there
+    Denied(String),
+}
+
+fn timestamp_today<T: TimeZone>(tz: T, time: NaiveTime) -> Option<i64> {
+    Utc::now()
+        .with_timezone(&tz)
+        .date_naive()
+        .and_time(time)
+        .and_local_timezone(tz)
+        .earliest()
+        .map(|dt| dt.timestamp())
+}
+
+// Splits channels into IDs (mapped to themselves) and names, each mapped to every
+// spelling it's configured as, ie. both `#metal` and `metal`
+fn split_channels(channels: &[String]) -> (HashMap<String, String>, HashMap<String, Vec<String>>) {
+    let mut ids = HashMap::new();
+    let mut names: HashMap<String, Vec<String>> = HashMap::new();
+
+    for channel in channels {
+        if is_channel_id(channel) {
+            ids.insert(channel.clone(), channel.clone());
+        } else {
+            names
+                .entry(channel.trim_start_matches('#').to_string())
+                .or_default()
+                .push(channel.clone());
+        }
+    }
+
+    (ids, names)
+}
+
+// Channel IDs are upper case alphanumerics starting with C (public), G
+// (private) or D (DM); names are lower case
+fn is_channel_id(channel: &str) -> bool {
+    channel.len() >= 9
+        && channel.starts_with(['C', 'G', 'D'])
+        && channel
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn channel_spellings() {
+        let (ids, names) = split_channels(&strings(&["#metal", "C0123456789", "metal", "doom"]));
+
+        assert_eq!(
+            ids.get("C0123456789").map(String::as_str),
+            Some("C0123456789")
+        );
+        assert_eq!(names["metal"], strings(&["#metal", "metal"]));
+        assert_eq!(names["doom"], strings(&["doom"]));
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn schedule_slots() {
+        let mut taken = vec![];
+        let mut from = 1000;
+
+        for _ in 0..40 {
+            let slot = next_slot(&taken, from);
+            taken.push(slot);
+            from = slot + 1;
+        }
+
+        assert_eq!(taken[..30], (1000..1030).collect::<Vec<i64>>());
+        // The rest wait until the first 5 minutes are over
+        assert_eq!(taken[30], 1300);
+
+        // Another digest's messages are skipped
+        assert_eq!(next_slot(&[1000, 1001, 1003], 1000), 1002);
+    }
 }
@@ -0,0 +1,197 @@
+use crate::config::{FilterProfile, Release, Subscription};
+use crate::normalize;
+
+// Returns the subset of releases that a subscription is interested in
+pub fn releases_for<'a>(subscription: &Subscription, releases: &[&'a Release]) -> Vec<&'a Release> {
+    releases
+        .iter()
+        .filter(|r| matches(&subscription.filter, r))
+        .copied()
+        .collect()
+}
+
+pub fn matches(filter: &FilterProfile, release: &Release) -> bool {
+    // Explicitly listed artists always get through
+    if filter
+        .artists
+        .iter()
+        .any(|a| normalize::same_artist(a, &release.artist))
+    {
+        return true;
+    }
+
+    // Only an artist list was given and this artist isn't on it
+    if !filter.artists.is_empty()
+        && filter.genres.is_empty()
+        && filter.countries.is_empty()
+        && filter.labels.is_empty()
+    {
+        return false;
+    }
+
+    let mut genres: Vec<String> = Vec::new();
+    let mut country = String::new();
+    let mut labels = vec![release.label.to_lowercase()];
+
+    if let Some(spotify) = &release.spotify {
+        genres.extend(spotify.genres.iter().map(|g| g.to_lowercase()));
+    }
+
+    if let Some(metallum) = &release.metallum {
+        genres.push(metallum.genre.to_lowercase());
+        country = metallum.country_origin.to_lowercase();
+        labels.push(metallum.last_label.to_lowercase());
+    }
+
+    any_keyword(&filter.genres, &genres)
+        && any_keyword(&filter.countries, &[country])
+        && any_keyword(&filter.labels, &labels)
+}
+
+// An empty keyword list matches everything
+fn any_keyword(keywords: &[String], values: &[String]) -> bool {
+    if keywords.is_empty() {
+        return true;
+    }
+
+    keywords.iter().any(|k| {
+        let k = k.to_lowercase();
+        values.iter().any(|v| v.contains(k.as_str()))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{MetallumArtistInfo, SpotifyArtistInfo, SubscriptionTarget};
+
+    fn release(artist: &str, label: &str, enriched: bool) -> Release {
+        let mut release: Release = serde_json::from_value(serde_json::json!({
+            "date": "2023-03-03",
+            "artist": artist,
+            "album": "Album",
+            "label": label,
+            "skip_reasons": [],
+        }))
+        .unwrap();
+
+        if enriched {
+            release.spotify = Some(SpotifyArtistInfo {
+                genres: vec!["Norwegian Black Metal".to_string()],
+                ..Default::default()
+            });
+            release.metallum = Some(MetallumArtistInfo {
+                genre: "Progressive Viking Metal".to_string(),
+                country_origin: "Norway".to_string(),
+                last_label: "Nuclear Blast".to_string(),
+                ..Default::default()
+            });
+        }
+
+        release
+    }
+
+    fn filter(
+        genres: &[&str],
+        countries: &[&str],
+        labels: &[&str],
+        artists: &[&str],
+    ) -> FilterProfile {
+        let list = |l: &[&str]| l.iter().map(|s| s.to_string()).collect();
+
+        FilterProfile {
+            genres: list(genres),
+            countries: list(countries),
+            labels: list(labels),
+            artists: list(artists),
+        }
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        assert!(matches(
+            &FilterProfile::default(),
+            &release("Enslaved", "", false)
+        ));
+        assert!(matches(
+            &FilterProfile::default(),
+            &release("Enslaved", "", true)
+        ));
+    }
+
+    #[test]
+    fn keywords() {
+        let enslaved = release("Enslaved", "", true);
+
+        // Case-insensitive parts of Spotify or Metal Archives genres
+        assert!(matches(&filter(&["BLACK"], &[], &[], &[]), &enslaved));
+        assert!(matches(&filter(&["viking"], &[], &[], &[]), &enslaved));
+        assert!(!matches(&filter(&["doom"], &[], &[], &[]), &enslaved));
+
+        // Any keyword of a list, but every non-empty list
+        assert!(matches(
+            &filter(&["doom", "viking"], &["norway"], &[], &[]),
+            &enslaved
+        ));
+        assert!(!matches(
+            &filter(&["viking"], &["sweden"], &[], &[]),
+            &enslaved
+        ));
+
+        // Labels match the release's label or the band's last label
+        assert!(matches(
+            &filter(&[], &[], &["nuclear blast"], &[]),
+            &enslaved
+        ));
+        assert!(matches(
+            &filter(&[], &[], &["relapse"], &[]),
+            &release("Mare", "Relapse", false)
+        ));
+
+        // Nothing to match genres and countries against before enrichment
+        assert!(!matches(
+            &filter(&["viking"], &[], &[], &[]),
+            &release("Enslaved", "", false)
+        ));
+        assert!(!matches(
+            &filter(&[], &["norway"], &[], &[]),
+            &release("Enslaved", "", false)
+        ));
+    }
+
+    #[test]
+    fn artists() {
+        let only_artists = filter(&[], &[], &[], &["Enslaved"]);
+
+        assert!(matches(&only_artists, &release("ENSLAVED", "", false)));
+        assert!(!matches(&only_artists, &release("Opeth", "", true)));
+
+        // Listed artists get through other filters; others still have to match them
+        let with_genres = filter(&["doom"], &[], &[], &["Enslaved"]);
+
+        assert!(matches(&with_genres, &release("Enslaved", "", true)));
+        assert!(!matches(&with_genres, &release("Opeth", "", true)));
+    }
+
+    #[test]
+    fn subscription_releases() {
+        let subscription = Subscription {
+            name: "labels".to_string(),
+            target: SubscriptionTarget::User("U123".to_string()),
+            filter: filter(&[], &[], &["relapse"], &[]),
+        };
+
+        let releases = [
+            release("Enslaved", "Nuclear Blast", true),
+            release("Mare", "Relapse", false),
+        ];
+        let releases: Vec<&Release> = releases.iter().collect();
+
+        let matching: Vec<&str> = releases_for(&subscription, &releases)
+            .iter()
+            .map(|r| r.artist.as_str())
+            .collect();
+
+        assert_eq!(matching, ["Mare"]);
+    }
+}
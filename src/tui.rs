@@ -0,0 +1,474 @@
+use crate::config::{self, Config, Release, Triage};
+use crate::{release, AppError};
+use chrono::{Local, NaiveDate};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use std::io;
+
+const HELP: &str = "↑/↓ move  ←/→ day  a all days  / filter  tab filter field  \
+                    s skip  w want  l listened  q quit";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Genre,
+    Country,
+    Label,
+}
+
+impl Field {
+    fn next(self) -> Self {
+        match self {
+            Field::Genre => Field::Country,
+            Field::Country => Field::Label,
+            Field::Label => Field::Genre,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Genre => "genre",
+            Field::Country => "country",
+            Field::Label => "label",
+        }
+    }
+
+    fn value(self, release: &Release) -> &str {
+        match (self, &release.metallum) {
+            (Field::Genre, Some(m)) => &m.genre,
+            (Field::Country, Some(m)) => &m.country_origin,
+            (Field::Label, _) => &release.label,
+            _ => "",
+        }
+    }
+}
+
+struct App {
+    config: Config,
+    dates: Vec<NaiveDate>,
+    date: usize,
+    all_days: bool,
+    field: Field,
+    filter: String,
+    editing: bool,
+    list: ListState,
+    status: String,
+}
+
+impl App {
+    fn new(config: Config) -> Self {
+        let mut dates: Vec<NaiveDate> = config.releases.iter().map(|r| r.date).collect();
+        dates.sort();
+        dates.dedup();
+
+        // Start on today, or the next day with releases
+        let today = Local::now().date_naive();
+        let date = dates
+            .iter()
+            .position(|d| *d >= today)
+            .unwrap_or(dates.len().saturating_sub(1));
+
+        let mut app = Self {
+            config,
+            dates,
+            date,
+            all_days: false,
+            field: Field::Genre,
+            filter: String::new(),
+            editing: false,
+            list: ListState::default(),
+            status: String::new(),
+        };
+
+        app.reset_selection();
+
+        app
+    }
+
+    // Indices into config.releases of the releases on screen
+    fn visible(&self) -> Vec<usize> {
+        let filter = self.filter.to_lowercase();
+
+        self.config
+            .releases
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| self.all_days || Some(&r.date) == self.dates.get(self.date))
+            .filter(|(_, r)| {
+                filter.is_empty() || self.field.value(r).to_lowercase().contains(&filter)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn selected(&self) -> Option<usize> {
+        self.list
+            .selected()
+            .and_then(|i| self.visible().get(i).copied())
+    }
+
+    fn reset_selection(&mut self) {
+        let selected = if self.visible().is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+
+        self.list.select(selected);
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let len = self.visible().len();
+
+        if len == 0 {
+            return;
+        }
+
+        let current = self.list.selected().unwrap_or(0) as isize;
+        self.list
+            .select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+    }
+
+    fn move_date(&mut self, delta: isize) {
+        if self.dates.is_empty() {
+            return;
+        }
+
+        self.all_days = false;
+        self.date = (self.date as isize + delta).clamp(0, self.dates.len() as isize - 1) as usize;
+        self.reset_selection();
+    }
+
+    // Marks are toggled; they are saved right away so nothing is lost on quit.
+    // Only the mark is written, to the store as it is now, since the daemon or
+    // a cron run may have saved it since the TUI was started.
+    fn mark(&mut self, triage: Triage) {
+        let index = match self.selected() {
+            Some(index) => index,
+            None => return,
+        };
+
+        let release = &mut self.config.releases[index];
+
+        release.triage = if release.triage == Some(triage) {
+            None
+        } else {
+            Some(triage)
+        };
+
+        let marked = release.clone();
+
+        let result = config::update(|config| {
+            match config
+                .releases
+                .iter_mut()
+                .find(|r| release::same(r, &marked))
+            {
+                Some(release) => {
+                    release.triage = marked.triage;
+                    Ok(())
+                }
+                None => Err(AppError::GenericError(format!(
+                    "Release '{}' is no longer in the store",
+                    release::id(&marked)
+                ))),
+            }
+        });
+
+        self.status = match result {
+            Ok(_) => String::new(),
+            Err(e) => format!("Could not save: {}", e),
+        };
+    }
+
+    // Returns false when the app should quit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if self.editing {
+            match key.code {
+                KeyCode::Enter => self.editing = false,
+                KeyCode::Esc => {
+                    self.editing = false;
+                    self.filter.clear();
+                }
+                KeyCode::Tab => self.field = self.field.next(),
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+
+            self.reset_selection();
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-10),
+            KeyCode::PageDown => self.move_selection(10),
+            KeyCode::Left => self.move_date(-1),
+            KeyCode::Right => self.move_date(1),
+            KeyCode::Char('a') => {
+                self.all_days = !self.all_days;
+                self.reset_selection();
+            }
+            KeyCode::Char('/') => self.editing = true,
+            KeyCode::Tab => {
+                self.field = self.field.next();
+                self.reset_selection();
+            }
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.reset_selection();
+            }
+            KeyCode::Char('s') => self.mark(Triage::Skip),
+            KeyCode::Char('w') => self.mark(Triage::Want),
+            KeyCode::Char('l') => self.mark(Triage::Listened),
+            _ => {}
+        }
+
+        true
+    }
+}
+
+pub fn run() -> Result<(), AppError> {
+    let mut app = App::new(config::load_config()?);
+
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let result = event_loop(&mut terminal, &mut app);
+
+    // Restore the terminal even if drawing failed
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    result
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+) -> Result<(), AppError> {
+    loop {
+        terminal.draw(|f| draw(f, app))?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !app.handle_key(key) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn draw(f: &mut Frame, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .split(f.size());
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+        .split(rows[1]);
+
+    // Header: day and filter
+    let day = match (app.all_days, app.dates.get(app.date)) {
+        (true, _) => "all days".to_string(),
+        (false, Some(date)) => date.format("%a %Y-%m-%d").to_string(),
+        (false, None) => "no releases".to_string(),
+    };
+
+    let mut header = vec![Span::styled(
+        format!(" metalpal | {} ", day),
+        Style::default().add_modifier(Modifier::BOLD),
+    )];
+
+    if app.editing || !app.filter.is_empty() {
+        header.push(Span::raw(format!(
+            "| {}: {}{}",
+            app.field.name(),
+            app.filter,
+            if app.editing { "_" } else { "" }
+        )));
+    }
+
+    f.render_widget(Paragraph::new(Line::from(header)), rows[0]);
+
+    // Release list
+    let items: Vec<ListItem> = app
+        .visible()
+        .iter()
+        .map(|&i| list_item(&app.config.releases[i], app.all_days))
+        .collect();
+
+    let count = items.len();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" Releases ({}) ", count)),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    f.render_stateful_widget(list, columns[0], &mut app.list);
+
+    // Detail pane
+    let detail = app
+        .selected()
+        .map(|i| detail(&app.config.releases[i]))
+        .unwrap_or_default();
+
+    f.render_widget(
+        Paragraph::new(detail)
+            .block(Block::default().borders(Borders::ALL).title(" Details "))
+            .wrap(Wrap { trim: false }),
+        columns[1],
+    );
+
+    // Footer: errors take precedence over help
+    let footer = if app.status.is_empty() {
+        Span::styled(HELP, Style::default().fg(Color::DarkGray))
+    } else {
+        Span::styled(app.status.as_str(), Style::default().fg(Color::Red))
+    };
+
+    f.render_widget(Paragraph::new(Line::from(footer)), rows[2]);
+}
+
+fn list_item(release: &Release, with_date: bool) -> ListItem<'static> {
+    let (mark, color) = match release.triage {
+        Some(Triage::Skip) => ("S", Color::Red),
+        Some(Triage::Want) => ("W", Color::Green),
+        Some(Triage::Listened) => ("L", Color::Blue),
+        None => (" ", Color::Reset),
+    };
+
+    let mut spans = vec![Span::styled(
+        format!("[{}] ", mark),
+        Style::default().fg(color),
+    )];
+
+    if with_date {
+        spans.push(Span::raw(format!("{} ", release.date)));
+    }
+
+    let name = format!("{} - {}", release.artist, release.album);
+
+    // Releases filtered out by metalpal are dimmed
+    if release.skip {
+        spans.push(Span::styled(name, Style::default().fg(Color::DarkGray)));
+    } else {
+        spans.push(Span::raw(name));
+    }
+
+    ListItem::new(Line::from(spans))
+}
+
+fn detail(release: &Release) -> Vec<Line<'static>> {
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{}: ", name), bold),
+            Span::raw(value),
+        ])
+    };
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!("{} - {}", release.artist, release.album),
+            bold.fg(Color::Yellow),
+        )),
+        field("Date", release.date.to_string()),
+        field("Label", release.label.clone()),
+    ];
+
+    if let Some(status) = release.status {
+        lines.push(field("Status", format!("{:?}", status).to_lowercase()));
+    }
+
+    if !release.date_history.is_empty() {
+        let dates: Vec<String> = release
+            .date_history
+            .iter()
+            .map(|c| c.from.to_string())
+            .collect();
+
+        lines.push(field("Previously", dates.join(", ")));
+    }
+
+    if let Some(triage) = release.triage {
+        lines.push(field("Marked", format!("{:?}", triage).to_lowercase()));
+    }
+
+    if release.skip {
+        lines.push(field("Skipped", release.skip_reasons.join("; ")));
+    }
+
+    if let Some(s) = &release.spotify {
+        lines.push(Line::default());
+        lines.push(Line::from(Span::styled("Spotify", bold.fg(Color::Green))));
+        lines.push(field("Popularity", s.popularity.to_string()));
+        lines.push(field("Followers", s.followers.to_string()));
+        lines.push(field("Genres", s.genres.join(", ")));
+        lines.push(field("URL", s.url.clone()));
+    }
+
+    if let Some(m) = &release.metallum {
+        lines.push(Line::default());
+        lines.push(Line::from(Span::styled(
+            "Metal Archives",
+            bold.fg(Color::Red),
+        )));
+
+        for (name, value) in [
+            ("Genre", &m.genre),
+            ("Country", &m.country_origin),
+            ("Location", &m.locations),
+            ("Formed in", &m.formed_in),
+            ("Years active", &m.years_active),
+            ("Themes", &m.themes),
+            ("Status", &m.status),
+            ("URL", &m.url),
+        ] {
+            if !value.is_empty() {
+                lines.push(field(name, value.clone()));
+            }
+        }
+
+        let bio = if m.description_long.is_empty() {
+            &m.description_short
+        } else {
+            &m.description_long
+        };
+
+        if !bio.is_empty() {
+            lines.push(Line::default());
+            lines.extend(bio.lines().map(|l| Line::from(l.to_string())));
+        }
+    }
+
+    if release.spotify.is_none() && release.metallum.is_none() {
+        lines.push(Line::default());
+        lines.push(Line::from("Not enriched yet"));
+    }
+
+    lines
+}